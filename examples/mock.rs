@@ -0,0 +1,127 @@
+use tokio::sync::mpsc::channel;
+use uuid::Uuid;
+
+use ble_peripheral_rust::{
+    adv::advertisement_data::{AdvertisementData, LocalName},
+    gatt::{characteristic::Characteristic, peripheral_event::PeripheralEvent, service::Service},
+    mock::Peripheral,
+    AttError, PeripheralBackend, SdpShortUuid,
+};
+
+#[tokio::main]
+async fn main() {
+    std::env::set_var("RUST_LOG", "info");
+    if let Err(err) = pretty_env_logger::try_init() {
+        eprintln!("WARNING: failed to initialize logging framework: {}", err);
+    }
+
+    let service_uuid = Uuid::from_sdp_short_uuid(0x1234_u16);
+    let char_uuid = Uuid::from_sdp_short_uuid(0x2A3D_u16);
+
+    let service = Service {
+        uuid: service_uuid,
+        primary: true,
+        characteristics: vec![Characteristic {
+            uuid: char_uuid,
+            ..Default::default()
+        }],
+    };
+
+    let (sender_tx, mut receiver_rx) = channel::<PeripheralEvent>(1);
+
+    let mut peripheral = Peripheral::new(sender_tx).await.unwrap();
+
+    tokio::spawn(async move {
+        while let Some(event) = receiver_rx.recv().await {
+            handle_updates(event);
+        }
+    });
+
+    peripheral.add_service(&service).await.unwrap();
+    peripheral
+        .start_advertising(&AdvertisementData {
+            local_name: Some(LocalName::Complete("RustBLE".to_string())),
+            service_uuids: vec![service.uuid],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let mut central = peripheral.virtual_central("central-1");
+    central.connect().await.unwrap();
+
+    let services = central.discover_services().await.unwrap();
+    log::info!("Discovered {} service(s)", services.len());
+
+    let value = central.read(service_uuid, char_uuid).await.unwrap();
+    log::info!("Read value: {:?}", String::from_utf8_lossy(&value));
+
+    central
+        .write(service_uuid, char_uuid, vec![1, 2, 3])
+        .await
+        .unwrap();
+
+    if let Err(err) = central.write(service_uuid, char_uuid, vec![]).await {
+        log::info!("Empty write rejected: {}", err);
+    }
+
+    let mut notifications = central.subscribe(service_uuid, char_uuid).await.unwrap();
+    let delivered = peripheral
+        .update_characteristic(service_uuid, char_uuid, vec![4, 5, 6])
+        .await
+        .unwrap();
+    log::info!("Notification delivered to {} central(s)", delivered);
+    log::info!("Notification: {:?}", notifications.recv().await);
+
+    // Values set in the database are pushed to subscribers and answer later reads
+    let database = peripheral.database();
+    database
+        .set(service_uuid, char_uuid, b"Stored".to_vec())
+        .unwrap();
+    log::info!("Notification: {:?}", notifications.recv().await);
+    let value = central.read(service_uuid, char_uuid).await.unwrap();
+    log::info!("Read value: {:?}", String::from_utf8_lossy(&value));
+
+    central.disconnect().await.unwrap();
+    peripheral.stop_advertising().await.unwrap();
+}
+
+pub fn handle_updates(update: PeripheralEvent) {
+    match update {
+        PeripheralEvent::DidReceiveReadRequest {
+            client,
+            service,
+            characteristic,
+            offset,
+            responder,
+        } => {
+            log::info!(
+                "DidReceiveReadRequest: {:?} {:?} {:?} {:?}",
+                client,
+                service,
+                characteristic,
+                offset
+            );
+            if let Err(err) = responder.send(Ok(String::from("hi").into())) {
+                log::error!("Error sending response: {:?}", err);
+            }
+        }
+        PeripheralEvent::DidReceiveWriteRequest {
+            client,
+            value,
+            responder,
+            ..
+        } => {
+            log::info!("DidReceiveWriteRequest: {:?} {:?}", client, value);
+            let response = if value.is_empty() {
+                Err(AttError::InvalidAttributeValueLength)
+            } else {
+                Ok(())
+            };
+            if let Err(err) = responder.send(response) {
+                log::error!("Error sending response: {:?}", err);
+            }
+        }
+        event => log::info!("{:?}", event),
+    }
+}
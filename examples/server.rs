@@ -3,6 +3,7 @@ use tokio::sync::mpsc::channel;
 use uuid::Uuid;
 
 use ble_peripheral_rust::{
+    adv::advertisement_data::{AdvertisementData, LocalName},
     gatt::{
         characteristic::Characteristic,
         descriptor::Descriptor,
@@ -10,7 +11,7 @@ use ble_peripheral_rust::{
         properties::{AttributePermission, CharacteristicProperty},
         service::Service,
     },
-    Peripheral, SdpShortUuid,
+    Peripheral, PeripheralBackend, SdpShortUuid,
 };
 
 #[tokio::main]
@@ -24,7 +25,7 @@ async fn main() {
     let characteristics: Vec<Characteristic> = vec![
         // Char 2A3D
         Characteristic {
-            uuid: Uuid::from_sdp_short_uuid(0x2A3D as u16),
+            uuid: Uuid::from_sdp_short_uuid(0x2A3D_u16),
             properties: vec![
                 CharacteristicProperty::Read,
                 CharacteristicProperty::Write,
@@ -35,14 +36,17 @@ async fn main() {
                 AttributePermission::Writeable,
             ],
             value: None,
-            descriptors: vec![Descriptor {
-                uuid: Uuid::from_sdp_short_uuid(0x2A13 as u16),
-                ..Default::default()
-            }],
+            descriptors: vec![
+                Descriptor {
+                    uuid: Uuid::from_sdp_short_uuid(0x2A13_u16),
+                    ..Default::default()
+                },
+                Descriptor::user_description("Sample"),
+            ],
         },
         // Char 1209
         Characteristic {
-            uuid: Uuid::from_sdp_short_uuid(0x1209 as u16),
+            uuid: Uuid::from_sdp_short_uuid(0x1209_u16),
             ..Default::default()
         },
     ];
@@ -71,7 +75,11 @@ async fn main() {
     peripheral.add_service(&service).await.unwrap();
 
     peripheral
-        .start_advertising("RustBLE", &[service.uuid])
+        .start_advertising(&AdvertisementData {
+            local_name: Some(LocalName::Complete("RustBLE".to_string())),
+            service_uuids: vec![service.uuid],
+            ..Default::default()
+        })
         .await
         .unwrap();
 
@@ -97,16 +105,24 @@ pub fn handle_updates(update: PeripheralEvent) {
         PeripheralEvent::DidAddService { service, error } => {
             log::info!("DidAddService: {:?} {:?}", service, error)
         }
+        PeripheralEvent::DidConnect { client } => {
+            log::info!("DidConnect: {:?}", client)
+        }
+        PeripheralEvent::DidDisconnect { client, reason } => {
+            log::info!("DidDisconnect: {:?} {:?}", client, reason)
+        }
         PeripheralEvent::DidSubscribeToCharacteristic {
             client,
             service,
             characteristic,
+            configuration,
         } => {
             log::info!(
-                "DidSubscribeToCharacteristic: {:?} {:?} {:?}",
+                "DidSubscribeToCharacteristic: {:?} {:?} {:?} {:?}",
                 client,
                 service,
-                characteristic
+                characteristic,
+                configuration
             )
         }
         PeripheralEvent::DidUnsubscribeFromCharacteristic {
@@ -125,15 +141,17 @@ pub fn handle_updates(update: PeripheralEvent) {
             client,
             service,
             characteristic,
+            offset,
             responder,
         } => {
             log::info!(
-                "DidReceiveReadRequest: {:?} {:?} {:?}",
+                "DidReceiveReadRequest: {:?} {:?} {:?} {:?}",
                 client,
                 service,
-                characteristic
+                characteristic,
+                offset
             );
-            if let Err(err) = responder.send(String::from("hi").into()) {
+            if let Err(err) = responder.send(Ok(String::from("hi").into())) {
                 log::error!("Error sending response: {:?}", err);
             }
         }
@@ -142,6 +160,7 @@ pub fn handle_updates(update: PeripheralEvent) {
             service,
             characteristic,
             value,
+            responder,
         } => {
             log::info!(
                 "DidReceiveWriteRequest: {:?} {:?} {:?} {:?}",
@@ -149,6 +168,63 @@ pub fn handle_updates(update: PeripheralEvent) {
                 service,
                 characteristic,
                 value
+            );
+            if let Err(err) = responder.send(Ok(())) {
+                log::error!("Error sending response: {:?}", err);
+            }
+        }
+        PeripheralEvent::DidReceiveDescriptorReadRequest {
+            client,
+            service,
+            characteristic,
+            descriptor,
+            offset,
+            responder,
+        } => {
+            log::info!(
+                "DidReceiveDescriptorReadRequest: {:?} {:?} {:?} {:?} {:?}",
+                client,
+                service,
+                characteristic,
+                descriptor,
+                offset
+            );
+            if let Err(err) = responder.send(Ok(String::from("hi").into())) {
+                log::error!("Error sending response: {:?}", err);
+            }
+        }
+        PeripheralEvent::DidReceiveDescriptorWriteRequest {
+            client,
+            service,
+            characteristic,
+            descriptor,
+            offset,
+            value,
+            responder,
+        } => {
+            log::info!(
+                "DidReceiveDescriptorWriteRequest: {:?} {:?} {:?} {:?} {:?} {:?}",
+                client,
+                service,
+                characteristic,
+                descriptor,
+                offset,
+                value
+            );
+            if let Err(err) = responder.send(Ok(())) {
+                log::error!("Error sending response: {:?}", err);
+            }
+        }
+        PeripheralEvent::DidCancelPreparedWrite {
+            client,
+            service,
+            characteristic,
+        } => {
+            log::info!(
+                "DidCancelPreparedWrite: {:?} {:?} {:?}",
+                client,
+                service,
+                characteristic
             )
         }
     }
@@ -0,0 +1,484 @@
+use crate::{Error, ErrorType};
+use uuid::Uuid;
+
+/// AD types from the Bluetooth assigned numbers
+pub mod ad_type {
+    pub const FLAGS: u8 = 0x01;
+    pub const INCOMPLETE_UUIDS_16: u8 = 0x02;
+    pub const COMPLETE_UUIDS_16: u8 = 0x03;
+    pub const INCOMPLETE_UUIDS_32: u8 = 0x04;
+    pub const COMPLETE_UUIDS_32: u8 = 0x05;
+    pub const INCOMPLETE_UUIDS_128: u8 = 0x06;
+    pub const COMPLETE_UUIDS_128: u8 = 0x07;
+    pub const SHORTENED_LOCAL_NAME: u8 = 0x08;
+    pub const COMPLETE_LOCAL_NAME: u8 = 0x09;
+    pub const TX_POWER_LEVEL: u8 = 0x0A;
+    pub const SOLICITED_UUIDS_16: u8 = 0x14;
+    pub const SOLICITED_UUIDS_128: u8 = 0x15;
+    pub const SERVICE_DATA_16: u8 = 0x16;
+    pub const APPEARANCE: u8 = 0x19;
+    pub const ADVERTISING_INTERVAL: u8 = 0x1A;
+    pub const SOLICITED_UUIDS_32: u8 = 0x1F;
+    pub const SERVICE_DATA_32: u8 = 0x20;
+    pub const SERVICE_DATA_128: u8 = 0x21;
+    pub const URI: u8 = 0x24;
+    pub const MANUFACTURER_DATA: u8 = 0xFF;
+}
+
+/// Flags bit telling scanners the peripheral is in general discoverable mode
+pub const LE_GENERAL_DISCOVERABLE: u8 = 0x02;
+/// Flags bit telling scanners the peripheral is LE only
+pub const BR_EDR_NOT_SUPPORTED: u8 = 0x04;
+
+// Codes replacing the scheme of a URI, "empty scheme" keeps the URI as is
+const URI_EMPTY_SCHEME: char = '\u{01}';
+const URI_SCHEMES: [(char, &str); 2] = [('\u{16}', "http:"), ('\u{17}', "https:")];
+
+/// One length-type-value structure of advertising data or a scan response.
+/// Multi-byte values are little endian on the air
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdStructure {
+    Flags(u8),
+    IncompleteUuids16(Vec<u16>),
+    CompleteUuids16(Vec<u16>),
+    IncompleteUuids32(Vec<u32>),
+    CompleteUuids32(Vec<u32>),
+    IncompleteUuids128(Vec<Uuid>),
+    CompleteUuids128(Vec<Uuid>),
+    ShortenedLocalName(String),
+    CompleteLocalName(String),
+    /// In dBm
+    TxPowerLevel(i8),
+    SolicitedUuids16(Vec<u16>),
+    SolicitedUuids32(Vec<u32>),
+    SolicitedUuids128(Vec<Uuid>),
+    ServiceData16(u16, Vec<u8>),
+    ServiceData32(u32, Vec<u8>),
+    ServiceData128(Uuid, Vec<u8>),
+    Appearance(u16),
+    /// In units of 0.625 ms
+    AdvertisingInterval(u16),
+    /// Complete URI, its scheme is compressed on the air where a code is assigned
+    Uri(String),
+    /// Company identifier followed by its data
+    ManufacturerData(u16, Vec<u8>),
+    /// Any type without a variant of its own
+    Other(u8, Vec<u8>),
+}
+
+impl AdStructure {
+    pub fn ad_type(&self) -> u8 {
+        match self {
+            AdStructure::Flags(_) => ad_type::FLAGS,
+            AdStructure::IncompleteUuids16(_) => ad_type::INCOMPLETE_UUIDS_16,
+            AdStructure::CompleteUuids16(_) => ad_type::COMPLETE_UUIDS_16,
+            AdStructure::IncompleteUuids32(_) => ad_type::INCOMPLETE_UUIDS_32,
+            AdStructure::CompleteUuids32(_) => ad_type::COMPLETE_UUIDS_32,
+            AdStructure::IncompleteUuids128(_) => ad_type::INCOMPLETE_UUIDS_128,
+            AdStructure::CompleteUuids128(_) => ad_type::COMPLETE_UUIDS_128,
+            AdStructure::ShortenedLocalName(_) => ad_type::SHORTENED_LOCAL_NAME,
+            AdStructure::CompleteLocalName(_) => ad_type::COMPLETE_LOCAL_NAME,
+            AdStructure::TxPowerLevel(_) => ad_type::TX_POWER_LEVEL,
+            AdStructure::SolicitedUuids16(_) => ad_type::SOLICITED_UUIDS_16,
+            AdStructure::SolicitedUuids32(_) => ad_type::SOLICITED_UUIDS_32,
+            AdStructure::SolicitedUuids128(_) => ad_type::SOLICITED_UUIDS_128,
+            AdStructure::ServiceData16(_, _) => ad_type::SERVICE_DATA_16,
+            AdStructure::ServiceData32(_, _) => ad_type::SERVICE_DATA_32,
+            AdStructure::ServiceData128(_, _) => ad_type::SERVICE_DATA_128,
+            AdStructure::Appearance(_) => ad_type::APPEARANCE,
+            AdStructure::AdvertisingInterval(_) => ad_type::ADVERTISING_INTERVAL,
+            AdStructure::Uri(_) => ad_type::URI,
+            AdStructure::ManufacturerData(_, _) => ad_type::MANUFACTURER_DATA,
+            AdStructure::Other(ad_type, _) => *ad_type,
+        }
+    }
+
+    /// Bytes following the type
+    pub fn data(&self) -> Vec<u8> {
+        match self {
+            AdStructure::Flags(flags) => vec![*flags],
+            AdStructure::IncompleteUuids16(uuids)
+            | AdStructure::CompleteUuids16(uuids)
+            | AdStructure::SolicitedUuids16(uuids) => {
+                uuids.iter().flat_map(|uuid| uuid.to_le_bytes()).collect()
+            }
+            AdStructure::IncompleteUuids32(uuids)
+            | AdStructure::CompleteUuids32(uuids)
+            | AdStructure::SolicitedUuids32(uuids) => {
+                uuids.iter().flat_map(|uuid| uuid.to_le_bytes()).collect()
+            }
+            AdStructure::IncompleteUuids128(uuids)
+            | AdStructure::CompleteUuids128(uuids)
+            | AdStructure::SolicitedUuids128(uuids) => uuids
+                .iter()
+                .flat_map(|uuid| uuid.as_u128().to_le_bytes())
+                .collect(),
+            AdStructure::ShortenedLocalName(name) | AdStructure::CompleteLocalName(name) => {
+                name.as_bytes().to_vec()
+            }
+            AdStructure::TxPowerLevel(level) => level.to_le_bytes().to_vec(),
+            AdStructure::ServiceData16(uuid, value) => [&uuid.to_le_bytes(), &value[..]].concat(),
+            AdStructure::ServiceData32(uuid, value) => [&uuid.to_le_bytes(), &value[..]].concat(),
+            AdStructure::ServiceData128(uuid, value) => {
+                [&uuid.as_u128().to_le_bytes(), &value[..]].concat()
+            }
+            AdStructure::Appearance(appearance) => appearance.to_le_bytes().to_vec(),
+            AdStructure::AdvertisingInterval(interval) => interval.to_le_bytes().to_vec(),
+            AdStructure::Uri(uri) => encode_uri(uri).into_bytes(),
+            AdStructure::ManufacturerData(company, value) => {
+                [&company.to_le_bytes(), &value[..]].concat()
+            }
+            AdStructure::Other(_, data) => data.clone(),
+        }
+    }
+
+    /// Length, type and data, as sent over the air
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let data = self.data();
+        let mut bytes = Vec::with_capacity(data.len() + 2);
+        // Structures this long fit into no packet, only their size matters
+        bytes.push((data.len() + 1) as u8);
+        bytes.push(self.ad_type());
+        bytes.extend(data);
+        bytes
+    }
+
+    /// Number of bytes the structure takes up in a packet
+    pub fn encoded_len(&self) -> usize {
+        self.data().len() + 2
+    }
+
+    /// Decode the data of a structure of the given type
+    pub fn from_data(ad_type: u8, data: &[u8]) -> Result<Self, Error> {
+        let structure = match ad_type {
+            ad_type::FLAGS => AdStructure::Flags(fixed::<1>(ad_type, data)?[0]),
+            ad_type::INCOMPLETE_UUIDS_16 => AdStructure::IncompleteUuids16(list(ad_type, data)?),
+            ad_type::COMPLETE_UUIDS_16 => AdStructure::CompleteUuids16(list(ad_type, data)?),
+            ad_type::INCOMPLETE_UUIDS_32 => AdStructure::IncompleteUuids32(list(ad_type, data)?),
+            ad_type::COMPLETE_UUIDS_32 => AdStructure::CompleteUuids32(list(ad_type, data)?),
+            ad_type::INCOMPLETE_UUIDS_128 => AdStructure::IncompleteUuids128(list(ad_type, data)?),
+            ad_type::COMPLETE_UUIDS_128 => AdStructure::CompleteUuids128(list(ad_type, data)?),
+            ad_type::SHORTENED_LOCAL_NAME => {
+                AdStructure::ShortenedLocalName(String::from_utf8_lossy(data).into_owned())
+            }
+            ad_type::COMPLETE_LOCAL_NAME => {
+                AdStructure::CompleteLocalName(String::from_utf8_lossy(data).into_owned())
+            }
+            ad_type::TX_POWER_LEVEL => {
+                AdStructure::TxPowerLevel(i8::from_le_bytes(fixed::<1>(ad_type, data)?))
+            }
+            ad_type::SOLICITED_UUIDS_16 => AdStructure::SolicitedUuids16(list(ad_type, data)?),
+            ad_type::SOLICITED_UUIDS_32 => AdStructure::SolicitedUuids32(list(ad_type, data)?),
+            ad_type::SOLICITED_UUIDS_128 => AdStructure::SolicitedUuids128(list(ad_type, data)?),
+            ad_type::SERVICE_DATA_16 => {
+                let (uuid, value) = split::<2>(ad_type, data)?;
+                AdStructure::ServiceData16(u16::from_le_bytes(uuid), value)
+            }
+            ad_type::SERVICE_DATA_32 => {
+                let (uuid, value) = split::<4>(ad_type, data)?;
+                AdStructure::ServiceData32(u32::from_le_bytes(uuid), value)
+            }
+            ad_type::SERVICE_DATA_128 => {
+                let (uuid, value) = split::<16>(ad_type, data)?;
+                AdStructure::ServiceData128(Uuid::from_u128(u128::from_le_bytes(uuid)), value)
+            }
+            ad_type::APPEARANCE => {
+                AdStructure::Appearance(u16::from_le_bytes(fixed::<2>(ad_type, data)?))
+            }
+            ad_type::ADVERTISING_INTERVAL => {
+                AdStructure::AdvertisingInterval(u16::from_le_bytes(fixed::<2>(ad_type, data)?))
+            }
+            ad_type::URI => AdStructure::Uri(decode_uri(&String::from_utf8_lossy(data))),
+            ad_type::MANUFACTURER_DATA => {
+                let (company, value) = split::<2>(ad_type, data)?;
+                AdStructure::ManufacturerData(u16::from_le_bytes(company), value)
+            }
+            _ => AdStructure::Other(ad_type, data.to_vec()),
+        };
+        Ok(structure)
+    }
+}
+
+/// Concatenate the structures into the payload of one packet
+pub fn encode(structures: &[AdStructure]) -> Vec<u8> {
+    structures.iter().flat_map(AdStructure::to_bytes).collect()
+}
+
+/// Split a packet payload into its structures, a zero length ends the significant part
+pub fn parse(mut bytes: &[u8]) -> Result<Vec<AdStructure>, Error> {
+    let mut structures = vec![];
+    while let Some((&len, rest)) = bytes.split_first() {
+        let len = usize::from(len);
+        if len == 0 {
+            break;
+        }
+        if len > rest.len() {
+            return Err(invalid(format!(
+                "Structure of {} bytes exceeds the {} bytes left",
+                len,
+                rest.len()
+            )));
+        }
+        structures.push(AdStructure::from_data(rest[0], &rest[1..len])?);
+        bytes = &rest[len..];
+    }
+    Ok(structures)
+}
+
+/// 16 or 32-bit alias of a uuid built on the Bluetooth base uuid
+pub(crate) fn short_uuid(uuid: Uuid) -> Option<u32> {
+    const BASE_UUID: u128 = 0x0000_0000_0000_1000_8000_0080_5F9B_34FB;
+    const ALIAS_MASK: u128 = 0xFFFF_FFFF << 96;
+    let value = uuid.as_u128();
+    if value & !ALIAS_MASK != BASE_UUID {
+        return None;
+    }
+    Some((value >> 96) as u32)
+}
+
+fn encode_uri(uri: &str) -> String {
+    for (code, scheme) in URI_SCHEMES {
+        if let Some(rest) = uri.strip_prefix(scheme) {
+            return format!("{}{}", code, rest);
+        }
+    }
+    format!("{}{}", URI_EMPTY_SCHEME, uri)
+}
+
+fn decode_uri(data: &str) -> String {
+    let mut chars = data.chars();
+    let Some(code) = chars.next() else {
+        return String::new();
+    };
+    let scheme = URI_SCHEMES
+        .iter()
+        .find(|(scheme_code, _)| *scheme_code == code)
+        .map_or("", |(_, scheme)| scheme);
+    // Unknown codes are kept, so the URI still shows which scheme was meant
+    match scheme.is_empty() && code != URI_EMPTY_SCHEME {
+        true => data.to_string(),
+        false => format!("{}{}", scheme, chars.as_str()),
+    }
+}
+
+/// Values of a uuid list, whose element size follows from the element type
+trait ListElement: Sized {
+    const SIZE: usize;
+    fn from_le_slice(bytes: &[u8]) -> Self;
+}
+
+impl ListElement for u16 {
+    const SIZE: usize = 2;
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        u16::from_le_bytes([bytes[0], bytes[1]])
+    }
+}
+
+impl ListElement for u32 {
+    const SIZE: usize = 4;
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+}
+
+impl ListElement for Uuid {
+    const SIZE: usize = 16;
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        let mut value = [0; 16];
+        value.copy_from_slice(bytes);
+        Uuid::from_u128(u128::from_le_bytes(value))
+    }
+}
+
+fn list<T: ListElement>(ad_type: u8, data: &[u8]) -> Result<Vec<T>, Error> {
+    if !data.len().is_multiple_of(T::SIZE) {
+        return Err(invalid(format!(
+            "Type 0x{:02X} holds {}-byte values, got {} bytes",
+            ad_type,
+            T::SIZE,
+            data.len()
+        )));
+    }
+    Ok(data.chunks(T::SIZE).map(T::from_le_slice).collect())
+}
+
+fn fixed<const N: usize>(ad_type: u8, data: &[u8]) -> Result<[u8; N], Error> {
+    data.try_into().map_err(|_| {
+        invalid(format!(
+            "Type 0x{:02X} holds {} bytes, got {}",
+            ad_type,
+            N,
+            data.len()
+        ))
+    })
+}
+
+/// Leading fixed size value and the bytes after it
+fn split<const N: usize>(ad_type: u8, data: &[u8]) -> Result<([u8; N], Vec<u8>), Error> {
+    if data.len() < N {
+        return Err(invalid(format!(
+            "Type 0x{:02X} starts with {} bytes, got {}",
+            ad_type,
+            N,
+            data.len()
+        )));
+    }
+    let (head, rest) = data.split_at(N);
+    Ok((fixed::<N>(ad_type, head)?, rest.to_vec()))
+}
+
+fn invalid(description: String) -> Error {
+    Error::new(
+        "InvalidAdStructure".to_string(),
+        description,
+        ErrorType::Failed,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(structure: AdStructure, golden: &[u8]) {
+        assert_eq!(structure.to_bytes(), golden);
+        assert_eq!(structure.encoded_len(), golden.len());
+        assert_eq!(parse(golden).unwrap(), [structure]);
+    }
+
+    #[test]
+    fn flags() {
+        round_trip(AdStructure::Flags(0x06), &[0x02, 0x01, 0x06]);
+    }
+
+    #[test]
+    fn uuid_lists() {
+        round_trip(
+            AdStructure::IncompleteUuids16(vec![0x180D, 0x180F]),
+            &[0x05, 0x02, 0x0D, 0x18, 0x0F, 0x18],
+        );
+        round_trip(
+            AdStructure::CompleteUuids16(vec![0xFEAA]),
+            &[0x03, 0x03, 0xAA, 0xFE],
+        );
+        round_trip(
+            AdStructure::CompleteUuids32(vec![0x1234_5678]),
+            &[0x05, 0x05, 0x78, 0x56, 0x34, 0x12],
+        );
+        round_trip(
+            AdStructure::CompleteUuids128(vec![Uuid::parse_str(
+                "6e400001-b5a3-f393-e0a9-e50e24dcca9e",
+            )
+            .unwrap()]),
+            &[
+                0x11, 0x07, 0x9E, 0xCA, 0xDC, 0x24, 0x0E, 0xE5, 0xA9, 0xE0, 0x93, 0xF3, 0xA3, 0xB5,
+                0x01, 0x00, 0x40, 0x6E,
+            ],
+        );
+        round_trip(
+            AdStructure::SolicitedUuids16(vec![0x1812]),
+            &[0x03, 0x14, 0x12, 0x18],
+        );
+    }
+
+    #[test]
+    fn local_names() {
+        round_trip(
+            AdStructure::CompleteLocalName("RustBLE".to_string()),
+            &[0x08, 0x09, b'R', b'u', b's', b't', b'B', b'L', b'E'],
+        );
+        round_trip(
+            AdStructure::ShortenedLocalName("Ru".to_string()),
+            &[0x03, 0x08, b'R', b'u'],
+        );
+    }
+
+    #[test]
+    fn tx_power_level_is_signed() {
+        round_trip(AdStructure::TxPowerLevel(-8), &[0x02, 0x0A, 0xF8]);
+    }
+
+    #[test]
+    fn service_data() {
+        round_trip(
+            AdStructure::ServiceData16(0x180F, vec![0x64]),
+            &[0x04, 0x16, 0x0F, 0x18, 0x64],
+        );
+        round_trip(
+            AdStructure::ServiceData32(0x1234_5678, vec![]),
+            &[0x05, 0x20, 0x78, 0x56, 0x34, 0x12],
+        );
+    }
+
+    #[test]
+    fn appearance_and_interval() {
+        round_trip(AdStructure::Appearance(0x0341), &[0x03, 0x19, 0x41, 0x03]);
+        round_trip(
+            AdStructure::AdvertisingInterval(0x00A0),
+            &[0x03, 0x1A, 0xA0, 0x00],
+        );
+    }
+
+    #[test]
+    fn uri_scheme_is_compressed() {
+        let mut golden = vec![0x0D, 0x24, 0x17];
+        golden.extend(b"//rust-lang");
+        round_trip(AdStructure::Uri("https://rust-lang".to_string()), &golden);
+
+        let mut golden = vec![0x0D, 0x24, 0x01];
+        golden.extend(b"urn:example");
+        round_trip(AdStructure::Uri("urn:example".to_string()), &golden);
+    }
+
+    #[test]
+    fn manufacturer_data() {
+        round_trip(
+            AdStructure::ManufacturerData(0x004C, vec![0x02, 0x15]),
+            &[0x05, 0xFF, 0x4C, 0x00, 0x02, 0x15],
+        );
+    }
+
+    #[test]
+    fn unknown_types_are_kept() {
+        round_trip(
+            AdStructure::Other(0x2A, vec![1, 2]),
+            &[0x03, 0x2A, 0x01, 0x02],
+        );
+    }
+
+    #[test]
+    fn packet_with_padding() {
+        let packet = [
+            0x02, 0x01, 0x06, 0x03, 0x03, 0x0D, 0x18, 0x05, 0x09, b'R', b'u', b's', b't', 0x00,
+            0x00,
+        ];
+        let structures = vec![
+            AdStructure::Flags(0x06),
+            AdStructure::CompleteUuids16(vec![0x180D]),
+            AdStructure::CompleteLocalName("Rust".to_string()),
+        ];
+        assert_eq!(parse(&packet).unwrap(), structures);
+        assert_eq!(encode(&structures), packet[..13]);
+    }
+
+    #[test]
+    fn malformed_structures_are_rejected() {
+        // Length runs past the end of the packet
+        assert!(parse(&[0x05, 0x09, b'R']).is_err());
+        // 16-bit uuid list with an odd number of bytes
+        assert!(parse(&[0x04, 0x03, 0x0D, 0x18, 0x0F]).is_err());
+        // Appearance is always two bytes
+        assert!(parse(&[0x02, 0x19, 0x41]).is_err());
+        // Manufacturer data needs a company identifier
+        assert!(parse(&[0x02, 0xFF, 0x4C]).is_err());
+    }
+
+    #[test]
+    fn short_uuids_are_recognized() {
+        let uuid = Uuid::parse_str("0000180d-0000-1000-8000-00805f9b34fb").unwrap();
+        assert_eq!(short_uuid(uuid), Some(0x180D));
+        let uuid = Uuid::parse_str("12345678-0000-1000-8000-00805f9b34fb").unwrap();
+        assert_eq!(short_uuid(uuid), Some(0x1234_5678));
+        let uuid = Uuid::parse_str("6e400001-b5a3-f393-e0a9-e50e24dcca9e").unwrap();
+        assert_eq!(short_uuid(uuid), None);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{Error, ErrorType};
+use std::{collections::BTreeMap, time::Duration};
+use uuid::Uuid;
+
+/// Shortest advertising interval a peripheral may request
+pub const MIN_INTERVAL: Duration = Duration::from_millis(20);
+/// Longest advertising interval a peripheral may request
+pub const MAX_INTERVAL: Duration = Duration::from_millis(10_240);
+/// Lowest TX power a peripheral may request, in dBm
+pub const MIN_TX_POWER: i16 = -127;
+/// Highest TX power a peripheral may request, in dBm
+pub const MAX_TX_POWER: i16 = 20;
+/// Longest duration and timeout, which have to be whole seconds
+pub const MAX_SECONDS: Duration = Duration::from_secs(u16::MAX as u64);
+
+/// Name of the peripheral as it appears in the advertisement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalName {
+    Complete(String),
+    /// Prefix of the complete name, for advertisements with little room left
+    Shortened(String),
+}
+
+impl LocalName {
+    pub fn name(&self) -> &str {
+        match self {
+            LocalName::Complete(name) | LocalName::Shortened(name) => name,
+        }
+    }
+}
+
+/// Content of an advertisement. Not every platform can send every field,
+/// `start_advertising` fails with an `Error` naming a field it cannot send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdvertisementData {
+    pub local_name: Option<LocalName>,
+    pub service_uuids: Vec<Uuid>,
+    /// Services the peripheral would like to use on a central
+    pub solicited_uuids: Vec<Uuid>,
+    pub service_data: BTreeMap<Uuid, Vec<u8>>,
+    /// Keyed by the company identifier assigned by the Bluetooth SIG
+    pub manufacturer_data: BTreeMap<u16, Vec<u8>>,
+    pub appearance: Option<u16>,
+    /// Let the stack add the TX power level it advertises with
+    pub include_tx_power: bool,
+    /// Set the general discoverable flag, centrals running a discovery ignore
+    /// advertisements without it
+    pub discoverable: bool,
+    /// Advertising interval the stack picks from, longer intervals save power
+    /// while shorter ones are found faster
+    pub min_interval: Option<Duration>,
+    pub max_interval: Option<Duration>,
+    /// Transmission power to advertise with, in dBm. The adapter may not support
+    /// setting it, or pick the closest power it can use
+    pub tx_power: Option<i16>,
+    /// How long this advertisement is sent at a time, when other advertisements
+    /// take turns with it, in whole seconds
+    pub duration: Option<Duration>,
+    /// Stop advertising after this time, in whole seconds
+    pub timeout: Option<Duration>,
+}
+
+impl Default for AdvertisementData {
+    fn default() -> Self {
+        AdvertisementData {
+            local_name: None,
+            service_uuids: Vec::new(),
+            solicited_uuids: Vec::new(),
+            service_data: BTreeMap::new(),
+            manufacturer_data: BTreeMap::new(),
+            appearance: None,
+            include_tx_power: false,
+            discoverable: true,
+            min_interval: None,
+            max_interval: None,
+            tx_power: None,
+            duration: None,
+            timeout: None,
+        }
+    }
+}
+
+impl AdvertisementData {
+    /// Reject timing and power values outside the ranges a peripheral may request
+    pub(crate) fn check_parameters(&self) -> Result<(), Error> {
+        for (interval, name) in [
+            (self.min_interval, "Minimum interval"),
+            (self.max_interval, "Maximum interval"),
+        ] {
+            if let Some(interval) = interval {
+                if !(MIN_INTERVAL..=MAX_INTERVAL).contains(&interval) {
+                    return Err(invalid_parameter(format!(
+                        "{} of {:?} is outside of [{:?}, {:?}]",
+                        name, interval, MIN_INTERVAL, MAX_INTERVAL
+                    )));
+                }
+            }
+        }
+        if let (Some(min_interval), Some(max_interval)) = (self.min_interval, self.max_interval) {
+            if min_interval > max_interval {
+                return Err(invalid_parameter(format!(
+                    "Minimum interval of {:?} is longer than the maximum interval of {:?}",
+                    min_interval, max_interval
+                )));
+            }
+        }
+
+        if let Some(tx_power) = self.tx_power {
+            if !(MIN_TX_POWER..=MAX_TX_POWER).contains(&tx_power) {
+                return Err(invalid_parameter(format!(
+                    "TX power of {} dBm is outside of [{}, {}] dBm",
+                    tx_power, MIN_TX_POWER, MAX_TX_POWER
+                )));
+            }
+        }
+
+        for (time, name) in [(self.duration, "Duration"), (self.timeout, "Timeout")] {
+            if let Some(time) = time {
+                if !(Duration::from_secs(1)..=MAX_SECONDS).contains(&time) {
+                    return Err(invalid_parameter(format!(
+                        "{} of {:?} is outside of [1s, {:?}]",
+                        name, time, MAX_SECONDS
+                    )));
+                }
+                if time.subsec_nanos() != 0 {
+                    return Err(invalid_parameter(format!(
+                        "{} of {:?} is not a whole number of seconds",
+                        name, time
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid_parameter(description: String) -> Error {
+    Error::new(
+        "InvalidAdvertisingParameter".to_string(),
+        description,
+        ErrorType::Failed,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_intervals(min_interval: Duration, max_interval: Duration) -> AdvertisementData {
+        AdvertisementData {
+            min_interval: Some(min_interval),
+            max_interval: Some(max_interval),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn intervals_are_checked_at_the_bounds() {
+        assert!(with_intervals(MIN_INTERVAL, MAX_INTERVAL)
+            .check_parameters()
+            .is_ok());
+        let below = MIN_INTERVAL - Duration::from_millis(1);
+        assert!(with_intervals(below, MAX_INTERVAL)
+            .check_parameters()
+            .is_err());
+        let above = MAX_INTERVAL + Duration::from_millis(1);
+        assert!(with_intervals(MIN_INTERVAL, above)
+            .check_parameters()
+            .is_err());
+        assert!(with_intervals(MAX_INTERVAL, MIN_INTERVAL)
+            .check_parameters()
+            .is_err());
+    }
+
+    #[test]
+    fn tx_power_is_checked_at_the_bounds() {
+        for (tx_power, valid) in [
+            (MIN_TX_POWER, true),
+            (MAX_TX_POWER, true),
+            (MIN_TX_POWER - 1, false),
+            (MAX_TX_POWER + 1, false),
+        ] {
+            let data = AdvertisementData {
+                tx_power: Some(tx_power),
+                ..Default::default()
+            };
+            assert_eq!(data.check_parameters().is_ok(), valid, "{} dBm", tx_power);
+        }
+    }
+
+    #[test]
+    fn duration_and_timeout_are_whole_seconds_in_range() {
+        for (time, valid) in [
+            (Duration::from_secs(1), true),
+            (MAX_SECONDS, true),
+            (Duration::from_millis(999), false),
+            (Duration::from_millis(1500), false),
+            (MAX_SECONDS + Duration::from_secs(1), false),
+        ] {
+            let duration = AdvertisementData {
+                duration: Some(time),
+                ..Default::default()
+            };
+            assert_eq!(duration.check_parameters().is_ok(), valid, "{:?}", time);
+            let timeout = AdvertisementData {
+                timeout: Some(time),
+                ..Default::default()
+            };
+            assert_eq!(timeout.check_parameters().is_ok(), valid, "{:?}", time);
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod ad_structure;
+pub mod advertisement_data;
+pub mod payload;
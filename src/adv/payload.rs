@@ -0,0 +1,440 @@
+use super::{
+    ad_structure::{short_uuid, AdStructure, BR_EDR_NOT_SUPPORTED, LE_GENERAL_DISCOVERABLE},
+    advertisement_data::{AdvertisementData, LocalName},
+};
+use crate::{Error, ErrorType};
+use uuid::Uuid;
+
+/// Bytes available in a legacy advertising packet, and again in its scan response
+pub const LEGACY_PAYLOAD_LEN: usize = 31;
+
+/// Which fields a stack may put into the scan response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Any field which does not fit into the advertising data
+    Overflow,
+    /// Only the local name, everything else has to fit into the advertising data.
+    /// BlueZ and CoreBluetooth lay out advertisements like this
+    NameInScanResponse,
+}
+
+/// Field of an advertisement, as named when it did not fit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdField {
+    Flags,
+    ServiceUuids,
+    SolicitedUuids,
+    ServiceData(Uuid),
+    ManufacturerData(u16),
+    Appearance,
+    TxPower,
+    LocalName,
+}
+
+/// Advertising data and scan response of a legacy advertisement
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Payload {
+    pub advertising_data: Vec<u8>,
+    pub scan_response: Vec<u8>,
+    /// The local name did not fit completely and is sent shortened
+    pub shortened_name: bool,
+    /// Fields which fit into neither packet
+    pub dropped: Vec<AdField>,
+}
+
+impl Payload {
+    /// Add the structure to the advertising data, or to the scan response if it is full
+    /// and the layout lets the field overflow
+    fn place(&mut self, field: AdField, structure: AdStructure, layout: Layout) {
+        let len = structure.encoded_len();
+        if self.advertising_data.len() + len <= LEGACY_PAYLOAD_LEN {
+            self.advertising_data.extend(structure.to_bytes());
+        } else if layout == Layout::Overflow && self.scan_response.len() + len <= LEGACY_PAYLOAD_LEN
+        {
+            self.scan_response.extend(structure.to_bytes());
+        } else if !self.dropped.contains(&field) {
+            self.dropped.push(field);
+        }
+    }
+
+    /// With `Layout::Overflow` the complete name goes wherever it fits, otherwise as much
+    /// of it as fits into the packet with the most room left. With
+    /// `Layout::NameInScanResponse` it always goes into the scan response
+    fn place_name(&mut self, local_name: &LocalName, layout: Layout) {
+        let structure = match local_name {
+            LocalName::Complete(name) => AdStructure::CompleteLocalName(name.clone()),
+            LocalName::Shortened(name) => AdStructure::ShortenedLocalName(name.clone()),
+        };
+        let name = local_name.name();
+        let room = |packet: &Vec<u8>| LEGACY_PAYLOAD_LEN.saturating_sub(packet.len() + 2);
+        let in_scan_response = match layout {
+            Layout::NameInScanResponse => true,
+            Layout::Overflow => {
+                if name.len() <= room(&self.advertising_data)
+                    || name.len() <= room(&self.scan_response)
+                {
+                    self.place(AdField::LocalName, structure, layout);
+                    return;
+                }
+                room(&self.scan_response) > room(&self.advertising_data)
+            }
+        };
+        let packet = match in_scan_response {
+            true => &mut self.scan_response,
+            false => &mut self.advertising_data,
+        };
+        if name.len() <= room(packet) {
+            packet.extend(structure.to_bytes());
+            return;
+        }
+        let shortened = truncate(name, room(packet));
+        if shortened.is_empty() {
+            self.dropped.push(AdField::LocalName);
+            return;
+        }
+        packet.extend(AdStructure::ShortenedLocalName(shortened.to_string()).to_bytes());
+        self.shortened_name = true;
+    }
+}
+
+/// Lay out the advertisement in legacy packets. Fields keep the order below and
+/// overflow into the scan response, the local name comes last to use up the room left
+pub fn encode(data: &AdvertisementData) -> Payload {
+    encode_with(data, Layout::Overflow)
+}
+
+/// Lay out the advertisement in legacy packets, only putting into the scan response
+/// what `layout` allows
+pub fn encode_with(data: &AdvertisementData, layout: Layout) -> Payload {
+    let mut payload = Payload::default();
+
+    let flags = match data.discoverable {
+        true => LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED,
+        false => BR_EDR_NOT_SUPPORTED,
+    };
+    payload.place(AdField::Flags, AdStructure::Flags(flags), layout);
+
+    let (uuids_16, uuids_32, uuids_128) = split_uuids(&data.service_uuids);
+    let lists = [
+        (!uuids_16.is_empty()).then_some(AdStructure::CompleteUuids16(uuids_16)),
+        (!uuids_32.is_empty()).then_some(AdStructure::CompleteUuids32(uuids_32)),
+        (!uuids_128.is_empty()).then_some(AdStructure::CompleteUuids128(uuids_128)),
+    ];
+    for list in lists.into_iter().flatten() {
+        payload.place(AdField::ServiceUuids, list, layout);
+    }
+
+    if let Some(appearance) = data.appearance {
+        payload.place(
+            AdField::Appearance,
+            AdStructure::Appearance(appearance),
+            layout,
+        );
+    }
+
+    // The level is filled in by the stack, only the size is known here
+    if data.include_tx_power {
+        payload.place(AdField::TxPower, AdStructure::TxPowerLevel(0), layout);
+    }
+
+    let (uuids_16, uuids_32, uuids_128) = split_uuids(&data.solicited_uuids);
+    let lists = [
+        (!uuids_16.is_empty()).then_some(AdStructure::SolicitedUuids16(uuids_16)),
+        (!uuids_32.is_empty()).then_some(AdStructure::SolicitedUuids32(uuids_32)),
+        (!uuids_128.is_empty()).then_some(AdStructure::SolicitedUuids128(uuids_128)),
+    ];
+    for list in lists.into_iter().flatten() {
+        payload.place(AdField::SolicitedUuids, list, layout);
+    }
+
+    for (uuid, value) in &data.service_data {
+        let structure = match short_uuid(*uuid) {
+            Some(short) => match u16::try_from(short) {
+                Ok(short) => AdStructure::ServiceData16(short, value.clone()),
+                Err(_) => AdStructure::ServiceData32(short, value.clone()),
+            },
+            None => AdStructure::ServiceData128(*uuid, value.clone()),
+        };
+        payload.place(AdField::ServiceData(*uuid), structure, layout);
+    }
+
+    for (company, value) in &data.manufacturer_data {
+        payload.place(
+            AdField::ManufacturerData(*company),
+            AdStructure::ManufacturerData(*company, value.clone()),
+            layout,
+        );
+    }
+
+    if let Some(local_name) = &data.local_name {
+        payload.place_name(local_name, layout);
+    }
+    payload
+}
+
+/// Fail when a field does not fit where the stack puts it,
+/// instead of leaving it to the stack to drop it silently
+pub(crate) fn check(data: &AdvertisementData, layout: Layout) -> Result<Payload, Error> {
+    let payload = encode_with(data, layout);
+    if !payload.dropped.is_empty() {
+        let message = match layout {
+            Layout::Overflow => format!(
+                "{:?} fit into neither the advertising data nor the scan response of {} bytes",
+                payload.dropped, LEGACY_PAYLOAD_LEN
+            ),
+            Layout::NameInScanResponse => format!(
+                "{:?} do not fit into the advertising data of {} bytes, \
+                 the stack only puts the local name into the scan response",
+                payload.dropped, LEGACY_PAYLOAD_LEN
+            ),
+        };
+        return Err(Error::new(
+            "AdvertisementTooLarge".to_string(),
+            message,
+            ErrorType::Failed,
+        ));
+    }
+    Ok(payload)
+}
+
+/// Sort uuids into 16, 32 and 128-bit ones, keeping their order
+fn split_uuids(uuids: &[Uuid]) -> (Vec<u16>, Vec<u32>, Vec<Uuid>) {
+    let mut split = (vec![], vec![], vec![]);
+    for uuid in uuids {
+        match short_uuid(*uuid) {
+            Some(short) => match u16::try_from(short) {
+                Ok(short) => split.0.push(short),
+                Err(_) => split.1.push(short),
+            },
+            None => split.2.push(*uuid),
+        }
+    }
+    split
+}
+
+/// Longest prefix of `name` with at most `len` bytes, cut at a character boundary
+fn truncate(name: &str, len: usize) -> &str {
+    let mut end = len.min(name.len());
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        adv::ad_structure::ad_type::{COMPLETE_LOCAL_NAME, SHORTENED_LOCAL_NAME},
+        SdpShortUuid,
+    };
+    use std::collections::BTreeMap;
+
+    fn named(name: &str) -> AdvertisementData {
+        AdvertisementData {
+            local_name: Some(LocalName::Complete(name.to_string())),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn small_advertisement_fits_into_advertising_data() {
+        let mut data = named("Rust");
+        data.service_uuids = vec![Uuid::from_sdp_short_uuid(0x180D_u16)];
+
+        let payload = encode(&data);
+        assert_eq!(
+            payload.advertising_data,
+            [
+                0x02, 0x01, 0x06, // Flags
+                0x03, 0x03, 0x0D, 0x18, // Complete 16-bit uuids
+                0x05, 0x09, b'R', b'u', b's', b't', // Complete local name
+            ]
+        );
+        assert!(payload.scan_response.is_empty());
+        assert!(!payload.shortened_name);
+        assert!(payload.dropped.is_empty());
+    }
+
+    #[test]
+    fn non_discoverable_advertisement_clears_discoverable_flag() {
+        let data = AdvertisementData {
+            discoverable: false,
+            ..Default::default()
+        };
+        assert_eq!(encode(&data).advertising_data, [0x02, 0x01, 0x04]);
+    }
+
+    #[test]
+    fn uuids_are_grouped_by_size() {
+        let long = Uuid::parse_str("6e400001-b5a3-f393-e0a9-e50e24dcca9e").unwrap();
+        let data = AdvertisementData {
+            service_uuids: vec![
+                Uuid::from_sdp_short_uuid(0x180F_u16),
+                long,
+                Uuid::from_sdp_short_uuid(0x1234_5678_u32),
+                Uuid::from_sdp_short_uuid(0x180A_u16),
+            ],
+            ..Default::default()
+        };
+
+        let mut expected = vec![0x02, 0x01, 0x06];
+        expected.extend([0x05, 0x03, 0x0F, 0x18, 0x0A, 0x18]);
+        expected.extend([0x05, 0x05, 0x78, 0x56, 0x34, 0x12]);
+        expected.extend([0x11, 0x07]);
+        expected.extend(long.as_u128().to_le_bytes());
+        // 3 + 6 + 6 + 18 bytes exceed 31, so the 128-bit list moves to the scan response
+        let payload = encode(&data);
+        assert_eq!(payload.advertising_data, expected[..15]);
+        assert_eq!(payload.scan_response, expected[15..]);
+    }
+
+    #[test]
+    fn overflow_goes_into_scan_response() {
+        let data = AdvertisementData {
+            manufacturer_data: BTreeMap::from([(0x004C, vec![0xAA; 20]), (0x0059, vec![0xBB; 4])]),
+            ..named("Sensor")
+        };
+
+        let payload = encode(&data);
+        // Flags (3) + first manufacturer data (24) leave 4 bytes, too few for the second (8)
+        assert_eq!(payload.advertising_data.len(), 27);
+        let mut scan_response = vec![0x07, 0xFF, 0x59, 0x00, 0xBB, 0xBB, 0xBB, 0xBB];
+        scan_response.extend([0x07, 0x09]);
+        scan_response.extend(b"Sensor");
+        assert_eq!(payload.scan_response, scan_response);
+        assert!(payload.dropped.is_empty());
+    }
+
+    #[test]
+    fn complete_name_moves_to_scan_response_before_being_shortened() {
+        let data = AdvertisementData {
+            manufacturer_data: BTreeMap::from([(0x004C, vec![0; 20])]),
+            ..named("A name of twenty bytes")
+        };
+
+        let payload = encode(&data);
+        assert_eq!(payload.advertising_data.len(), 27);
+        assert_eq!(payload.scan_response[1], COMPLETE_LOCAL_NAME);
+        assert_eq!(&payload.scan_response[2..], b"A name of twenty bytes");
+        assert!(!payload.shortened_name);
+    }
+
+    #[test]
+    fn long_name_is_shortened_to_the_room_left() {
+        let data = AdvertisementData {
+            manufacturer_data: BTreeMap::from([(0x004C, vec![0; 27])]),
+            ..named("A local name which is longer than any packet allows")
+        };
+
+        let payload = encode(&data);
+        // Manufacturer data fills the scan response, 26 bytes of name follow the flags
+        assert_eq!(payload.scan_response.len(), LEGACY_PAYLOAD_LEN);
+        assert_eq!(payload.advertising_data.len(), LEGACY_PAYLOAD_LEN);
+        assert_eq!(&payload.advertising_data[3..5], [27, SHORTENED_LOCAL_NAME]);
+        assert_eq!(
+            &payload.advertising_data[5..],
+            b"A local name which is long"
+        );
+        assert!(payload.shortened_name);
+        assert!(payload.dropped.is_empty());
+    }
+
+    #[test]
+    fn shortened_name_is_cut_at_a_character_boundary() {
+        let data = AdvertisementData {
+            manufacturer_data: BTreeMap::from([(0x004C, vec![0; 19]), (0x0059, vec![0; 27])]),
+            ..named("Größenmesser")
+        };
+
+        let payload = encode(&data);
+        // Three bytes of room end inside the 'ö', so only "Gr" is sent
+        assert_eq!(
+            &payload.advertising_data[26..],
+            [0x03, SHORTENED_LOCAL_NAME, b'G', b'r']
+        );
+        assert!(payload.shortened_name);
+    }
+
+    #[test]
+    fn given_shortened_name_keeps_its_type() {
+        let data = AdvertisementData {
+            local_name: Some(LocalName::Shortened("Rust".to_string())),
+            ..Default::default()
+        };
+
+        let payload = encode(&data);
+        assert_eq!(
+            &payload.advertising_data[3..5],
+            [0x05, SHORTENED_LOCAL_NAME]
+        );
+        assert!(!payload.shortened_name);
+    }
+
+    #[test]
+    fn fields_which_fit_nowhere_are_reported() {
+        let service = Uuid::from_sdp_short_uuid(0xFEAA_u16);
+        let data = AdvertisementData {
+            service_data: BTreeMap::from([(service, vec![0; 26])]),
+            manufacturer_data: BTreeMap::from([(0x004C, vec![0; 27]), (0x0059, vec![0; 2])]),
+            appearance: Some(0x0341),
+            ..Default::default()
+        };
+
+        let payload = encode(&data);
+        assert_eq!(payload.dropped, [AdField::ManufacturerData(0x004C)]);
+        assert!(check(&data, Layout::Overflow).is_err());
+    }
+
+    #[test]
+    fn check_accepts_what_fits() {
+        let data = AdvertisementData {
+            appearance: Some(0x0341),
+            include_tx_power: true,
+            ..named("Heart Rate")
+        };
+
+        let payload = check(&data, Layout::Overflow).unwrap();
+        assert_eq!(
+            payload.advertising_data,
+            [
+                0x02, 0x01, 0x06, // Flags
+                0x03, 0x19, 0x41, 0x03, // Appearance
+                0x02, 0x0A, 0x00, // TX power level
+                0x0B, 0x09, b'H', b'e', b'a', b'r', b't', b' ', b'R', b'a', b't', b'e',
+            ]
+        );
+    }
+
+    #[test]
+    fn only_the_name_goes_into_the_scan_response() {
+        let data = AdvertisementData {
+            manufacturer_data: BTreeMap::from([(0x004C, vec![0xAA; 20]), (0x0059, vec![0xBB; 4])]),
+            ..named("Sensor")
+        };
+
+        let payload = encode_with(&data, Layout::NameInScanResponse);
+        assert_eq!(payload.advertising_data.len(), 27);
+        let mut scan_response = vec![0x07, 0x09];
+        scan_response.extend(b"Sensor");
+        assert_eq!(payload.scan_response, scan_response);
+        assert_eq!(payload.dropped, [AdField::ManufacturerData(0x0059)]);
+        assert!(check(&data, Layout::NameInScanResponse).is_err());
+        assert!(check(&data, Layout::Overflow).is_ok());
+    }
+
+    #[test]
+    fn name_is_shortened_to_the_scan_response() {
+        let data = named("A local name which is longer than any packet allows");
+
+        let payload = encode_with(&data, Layout::NameInScanResponse);
+        assert_eq!(payload.advertising_data, [0x02, 0x01, 0x06]);
+        assert_eq!(&payload.scan_response[..2], [30, SHORTENED_LOCAL_NAME]);
+        assert_eq!(
+            &payload.scan_response[2..],
+            b"A local name which is longer "
+        );
+        assert!(payload.shortened_name);
+        assert!(payload.dropped.is_empty());
+    }
+}
@@ -5,6 +5,7 @@ pub enum ErrorType {
     Bluez,
     CoreBluetooth,
     Usb,
+    Mock,
     PermissionDenied,
     Failed,
     Unknown,
@@ -16,6 +17,7 @@ impl From<ErrorType> for &'static str {
             ErrorType::Bluez => "Bluez",
             ErrorType::CoreBluetooth => "CoreBluetooth",
             ErrorType::Usb => "USB",
+            ErrorType::Mock => "Mock",
             ErrorType::PermissionDenied => "PermissionDenied",
             ErrorType::Failed => "Failed",
             ErrorType::Unknown => "Unknown",
@@ -24,7 +26,7 @@ impl From<ErrorType> for &'static str {
 }
 
 impl fmt::Display for ErrorType {
-    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let error_type: &str = self.clone().into();
         write!(f, "<BlePeripheralRust {} Error>", error_type)
     }
@@ -67,7 +69,7 @@ impl Error {
 }
 
 impl fmt::Display for Error {
-    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let error_type: &str = self.error_type.clone().into();
         write!(
             f,
@@ -78,11 +80,153 @@ impl fmt::Display for Error {
 }
 
 impl error::Error for Error {
-    fn description(self: &Self) -> &str {
+    #[allow(
+        clippy::misnamed_getters,
+        reason = "the deprecated description names the error as well as describing it"
+    )]
+    fn description(&self) -> &str {
         &self.combined_description
     }
 
-    fn source(self: &Self) -> Option<&(dyn error::Error + 'static)> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         Some(&self.error_type)
     }
 }
+
+/// ATT error codes sent back to a central when a request fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttError {
+    InvalidHandle,
+    ReadNotPermitted,
+    WriteNotPermitted,
+    InvalidPdu,
+    InsufficientAuthentication,
+    RequestNotSupported,
+    InvalidOffset,
+    InsufficientAuthorization,
+    PrepareQueueFull,
+    AttributeNotFound,
+    AttributeNotLong,
+    InsufficientEncryptionKeySize,
+    InvalidAttributeValueLength,
+    UnlikelyError,
+    InsufficientEncryption,
+    UnsupportedGroupType,
+    InsufficientResources,
+    DatabaseOutOfSync,
+    ValueNotAllowed,
+    /// Application defined error, codes 0x80 to 0x9F. Codes outside that range
+    /// would be read as protocol errors and are sent as `UnlikelyError` instead
+    Application(u8),
+    WriteRequestRejected,
+    CccdImproperlyConfigured,
+    ProcedureAlreadyInProgress,
+    OutOfRange,
+}
+
+impl AttError {
+    pub fn code(&self) -> u8 {
+        match self {
+            AttError::InvalidHandle => 0x01,
+            AttError::ReadNotPermitted => 0x02,
+            AttError::WriteNotPermitted => 0x03,
+            AttError::InvalidPdu => 0x04,
+            AttError::InsufficientAuthentication => 0x05,
+            AttError::RequestNotSupported => 0x06,
+            AttError::InvalidOffset => 0x07,
+            AttError::InsufficientAuthorization => 0x08,
+            AttError::PrepareQueueFull => 0x09,
+            AttError::AttributeNotFound => 0x0A,
+            AttError::AttributeNotLong => 0x0B,
+            AttError::InsufficientEncryptionKeySize => 0x0C,
+            AttError::InvalidAttributeValueLength => 0x0D,
+            AttError::UnlikelyError => 0x0E,
+            AttError::InsufficientEncryption => 0x0F,
+            AttError::UnsupportedGroupType => 0x10,
+            AttError::InsufficientResources => 0x11,
+            AttError::DatabaseOutOfSync => 0x12,
+            AttError::ValueNotAllowed => 0x13,
+            AttError::Application(code @ 0x80..=0x9F) => *code,
+            AttError::Application(_) => AttError::UnlikelyError.code(),
+            AttError::WriteRequestRejected => 0xFC,
+            AttError::CccdImproperlyConfigured => 0xFD,
+            AttError::ProcedureAlreadyInProgress => 0xFE,
+            AttError::OutOfRange => 0xFF,
+        }
+    }
+
+    /// Parse a raw error code, returns None for Success and reserved codes
+    pub fn from_code(code: u8) -> Option<Self> {
+        let error = match code {
+            0x01 => AttError::InvalidHandle,
+            0x02 => AttError::ReadNotPermitted,
+            0x03 => AttError::WriteNotPermitted,
+            0x04 => AttError::InvalidPdu,
+            0x05 => AttError::InsufficientAuthentication,
+            0x06 => AttError::RequestNotSupported,
+            0x07 => AttError::InvalidOffset,
+            0x08 => AttError::InsufficientAuthorization,
+            0x09 => AttError::PrepareQueueFull,
+            0x0A => AttError::AttributeNotFound,
+            0x0B => AttError::AttributeNotLong,
+            0x0C => AttError::InsufficientEncryptionKeySize,
+            0x0D => AttError::InvalidAttributeValueLength,
+            0x0E => AttError::UnlikelyError,
+            0x0F => AttError::InsufficientEncryption,
+            0x10 => AttError::UnsupportedGroupType,
+            0x11 => AttError::InsufficientResources,
+            0x12 => AttError::DatabaseOutOfSync,
+            0x13 => AttError::ValueNotAllowed,
+            0x80..=0x9F => AttError::Application(code),
+            0xFC => AttError::WriteRequestRejected,
+            0xFD => AttError::CccdImproperlyConfigured,
+            0xFE => AttError::ProcedureAlreadyInProgress,
+            0xFF => AttError::OutOfRange,
+            _ => return None,
+        };
+        Some(error)
+    }
+}
+
+impl From<AttError> for u8 {
+    fn from(error: AttError) -> u8 {
+        error.code()
+    }
+}
+
+impl fmt::Display for AttError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} (0x{:02X})", self, self.code())
+    }
+}
+
+impl error::Error for AttError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_round_trip() {
+        for code in 0x00..=0xFF {
+            if let Some(error) = AttError::from_code(code) {
+                assert_eq!(error.code(), code);
+            }
+        }
+        assert_eq!(AttError::from_code(0x00), None);
+        assert_eq!(AttError::from_code(0x14), None);
+        assert_eq!(AttError::from_code(0xA0), None);
+        assert_eq!(AttError::from_code(0x80), Some(AttError::Application(0x80)));
+        assert_eq!(AttError::from_code(0x9F), Some(AttError::Application(0x9F)));
+    }
+
+    #[test]
+    fn application_codes_stay_in_their_range() {
+        assert_eq!(AttError::Application(0x80).code(), 0x80);
+        assert_eq!(AttError::Application(0x9F).code(), 0x9F);
+        assert_eq!(AttError::Application(0x01).code(), 0x0E);
+        assert_eq!(AttError::Application(0x7F).code(), 0x0E);
+        assert_eq!(AttError::Application(0xA0).code(), 0x0E);
+        assert_eq!(u8::from(AttError::Application(0xFF)), 0x0E);
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressType {
+    Public,
+    Random,
+}
+
+/// Central taking part in a GATT exchange with the peripheral.
+/// Equality and hashing only consider `id`, so a client stays the same map key
+/// while its other fields are refined
+#[derive(Debug, Clone)]
+pub struct Client {
+    /// Opaque identifier, stable for as long as the central is known to the stack.
+    /// This is the device address on BlueZ and the `CBCentral` identifier on CoreBluetooth
+    pub id: String,
+    /// Bluetooth address, not exposed by CoreBluetooth
+    pub address: Option<String>,
+    pub address_type: Option<AddressType>,
+    /// Negotiated ATT MTU
+    pub mtu: Option<u16>,
+}
+
+impl Client {
+    pub fn new(id: impl Into<String>) -> Self {
+        Client {
+            id: id.into(),
+            address: None,
+            address_type: None,
+            mtu: None,
+        }
+    }
+}
+
+impl PartialEq for Client {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Client {}
+
+impl Hash for Client {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
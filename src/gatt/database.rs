@@ -0,0 +1,173 @@
+use super::{properties::CharacteristicProperty, service::Service};
+use crate::{Error, ErrorType};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+#[derive(Debug)]
+struct Entry {
+    value: Option<Vec<u8>>,
+    pushes: bool,
+}
+
+#[derive(Debug, Default)]
+struct Values {
+    characteristics: HashMap<(Uuid, Uuid), Entry>,
+    descriptors: HashMap<(Uuid, Uuid, Uuid), Option<Vec<u8>>>,
+}
+
+/// New value of a notifying characteristic, to be pushed to its subscribers
+#[derive(Debug)]
+pub(crate) struct ValueUpdate {
+    pub(crate) service: Uuid,
+    pub(crate) characteristic: Uuid,
+    pub(crate) value: Vec<u8>,
+}
+
+/// Current values of the characteristics and descriptors added to a peripheral.
+/// Clones share the same values, reads of an attribute with a value are answered
+/// from here without a read request event.
+/// Writes the application accepts replace the value of a characteristic which has one
+#[derive(Debug, Clone)]
+pub struct GattDatabase {
+    values: Arc<Mutex<Values>>,
+    updates_tx: Arc<Mutex<Option<UnboundedSender<ValueUpdate>>>>,
+}
+
+impl GattDatabase {
+    /// Values set on notifying characteristics are delivered on the returned receiver
+    pub(crate) fn new() -> (Self, UnboundedReceiver<ValueUpdate>) {
+        let (updates_tx, updates_rx) = mpsc::unbounded_channel();
+        let database = GattDatabase {
+            values: Arc::new(Mutex::new(Values::default())),
+            updates_tx: Arc::new(Mutex::new(Some(updates_tx))),
+        };
+        (database, updates_rx)
+    }
+
+    /// Stop delivering updates, which ends the receiver even while clones are alive
+    pub(crate) fn close(&self) {
+        self.updates_tx.lock().unwrap().take();
+    }
+
+    /// Register the attributes of a service, with the values they were declared with
+    pub(crate) fn add_service(&self, service: &Service) {
+        let mut values = self.values.lock().unwrap();
+        for characteristic in &service.characteristics {
+            let pushes = characteristic.properties.iter().any(|property| {
+                matches!(
+                    property,
+                    CharacteristicProperty::Notify
+                        | CharacteristicProperty::NotifyEncryptionRequired
+                        | CharacteristicProperty::Indicate
+                        | CharacteristicProperty::IndicateEncryptionRequired
+                )
+            });
+            values.characteristics.insert(
+                (service.uuid, characteristic.uuid),
+                Entry {
+                    value: characteristic.value.clone(),
+                    pushes,
+                },
+            );
+            for descriptor in &characteristic.descriptors {
+                values.descriptors.insert(
+                    (service.uuid, characteristic.uuid, descriptor.uuid),
+                    descriptor.value.clone(),
+                );
+            }
+        }
+    }
+
+    pub fn get(&self, service: Uuid, characteristic: Uuid) -> Option<Vec<u8>> {
+        self.values
+            .lock()
+            .unwrap()
+            .characteristics
+            .get(&(service, characteristic))
+            .and_then(|entry| entry.value.clone())
+    }
+
+    /// Store the value, a characteristic which notifies or indicates also sends it
+    /// to its subscribers
+    pub fn set(&self, service: Uuid, characteristic: Uuid, value: Vec<u8>) -> Result<(), Error> {
+        let pushes = {
+            let mut values = self.values.lock().unwrap();
+            let entry = values
+                .characteristics
+                .get_mut(&(service, characteristic))
+                .ok_or_else(|| not_found("Characteristic"))?;
+            entry.value = Some(value.clone());
+            entry.pushes
+        };
+
+        if pushes {
+            let update = ValueUpdate {
+                service,
+                characteristic,
+                value,
+            };
+            let sent = match self.updates_tx.lock().unwrap().as_ref() {
+                Some(updates_tx) => updates_tx.send(update).is_ok(),
+                None => false,
+            };
+            if !sent {
+                log::warn!("Peripheral is gone, value was stored but not sent");
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep a value a central wrote and the application accepted, if the characteristic has
+    /// a stored value. Subscribers are not notified, which would echo the value to the writer
+    pub(crate) fn store_written(&self, service: Uuid, characteristic: Uuid, value: Vec<u8>) {
+        let mut values = self.values.lock().unwrap();
+        if let Some(entry) = values.characteristics.get_mut(&(service, characteristic)) {
+            if entry.value.is_some() {
+                entry.value = Some(value);
+            }
+        }
+    }
+
+    pub fn get_descriptor(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        descriptor: Uuid,
+    ) -> Option<Vec<u8>> {
+        self.values
+            .lock()
+            .unwrap()
+            .descriptors
+            .get(&(service, characteristic, descriptor))
+            .cloned()
+            .flatten()
+    }
+
+    pub fn set_descriptor(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        descriptor: Uuid,
+        value: Vec<u8>,
+    ) -> Result<(), Error> {
+        let mut values = self.values.lock().unwrap();
+        let entry = values
+            .descriptors
+            .get_mut(&(service, characteristic, descriptor))
+            .ok_or_else(|| not_found("Descriptor"))?;
+        entry.replace(value);
+        Ok(())
+    }
+}
+
+fn not_found(attribute: &str) -> Error {
+    Error::new(
+        format!("{}NotFound", attribute),
+        format!("{} is not part of any added service", attribute),
+        ErrorType::Failed,
+    )
+}
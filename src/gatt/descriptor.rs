@@ -1,28 +1,150 @@
-use super::properties::{AttributePermission, CharacteristicProperty};
+use super::{
+    presentation_format::{Format, Unit},
+    properties::DescriptorPermission,
+};
+use crate::SdpShortUuid;
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
 pub struct Descriptor {
     pub uuid: Uuid,
-    pub properties: Vec<CharacteristicProperty>,
-    pub permissions: Vec<AttributePermission>,
+    pub permissions: Vec<DescriptorPermission>,
     pub value: Option<Vec<u8>>,
 }
 
+impl Descriptor {
+    /// Characteristic Extended Properties (0x2900), the characteristic also needs
+    /// `CharacteristicProperty::ExtendedProperties`
+    pub fn extended_properties(reliable_write: bool, writable_auxiliaries: bool) -> Self {
+        let flags = u16::from(reliable_write) | u16::from(writable_auxiliaries) << 1;
+        Self::read_only(0x2900, flags.to_le_bytes().to_vec())
+    }
+
+    /// Characteristic User Description (0x2901), a readable name for the characteristic
+    pub fn user_description(description: impl Into<String>) -> Self {
+        Self::read_only(0x2901, description.into().into_bytes())
+    }
+
+    /// Characteristic Presentation Format (0x2904), the value is shown as
+    /// `value * 10^exponent` in `unit`. `description` is a value of `namespace`,
+    /// see [`BLUETOOTH_SIG_NAMESPACE`](super::presentation_format::BLUETOOTH_SIG_NAMESPACE)
+    pub fn presentation_format(
+        format: Format,
+        exponent: i8,
+        unit: Unit,
+        namespace: u8,
+        description: u16,
+    ) -> Self {
+        let mut value = vec![format as u8, exponent.to_le_bytes()[0]];
+        value.extend_from_slice(&(unit as u16).to_le_bytes());
+        value.push(namespace);
+        value.extend_from_slice(&description.to_le_bytes());
+        Self::read_only(0x2904, value)
+    }
+
+    /// Characteristic Aggregate Format (0x2905), listing the attribute handles of the
+    /// presentation format descriptors which together describe the value in order
+    pub fn aggregate_format(handles: &[u16]) -> Self {
+        let value = handles
+            .iter()
+            .flat_map(|handle| handle.to_le_bytes())
+            .collect();
+        Self::read_only(0x2905, value)
+    }
+
+    fn read_only(uuid: u16, value: Vec<u8>) -> Self {
+        Descriptor {
+            uuid: Uuid::from_sdp_short_uuid(uuid),
+            permissions: vec![DescriptorPermission::Readable],
+            value: Some(value),
+        }
+    }
+
+    /// Any read permission, an encrypted or authenticated read implies a plain one
+    pub(crate) fn is_readable(&self) -> bool {
+        self.permissions.iter().any(|permission| {
+            matches!(
+                permission,
+                DescriptorPermission::Readable
+                    | DescriptorPermission::ReadEncryptionRequired
+                    | DescriptorPermission::ReadAuthenticationRequired
+            )
+        })
+    }
+
+    pub(crate) fn is_writeable(&self) -> bool {
+        self.permissions.iter().any(|permission| {
+            matches!(
+                permission,
+                DescriptorPermission::Writeable
+                    | DescriptorPermission::WriteEncryptionRequired
+                    | DescriptorPermission::WriteAuthenticationRequired
+            )
+        })
+    }
+}
+
 impl Default for Descriptor {
     fn default() -> Self {
         Descriptor {
             uuid: Uuid::nil(),
-            properties: vec![
-                CharacteristicProperty::Read,
-                CharacteristicProperty::Write,
-                CharacteristicProperty::Notify,
-            ],
             permissions: vec![
-                AttributePermission::Readable,
-                AttributePermission::Writeable,
+                DescriptorPermission::Readable,
+                DescriptorPermission::Writeable,
             ],
             value: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gatt::presentation_format::BLUETOOTH_SIG_NAMESPACE;
+
+    #[test]
+    fn extended_properties_encoding() {
+        let descriptor = Descriptor::extended_properties(true, false);
+        assert_eq!(descriptor.uuid, Uuid::from_sdp_short_uuid(0x2900_u16));
+        assert_eq!(descriptor.value, Some(vec![0x01, 0x00]));
+        let descriptor = Descriptor::extended_properties(false, true);
+        assert_eq!(descriptor.value, Some(vec![0x02, 0x00]));
+        assert_eq!(descriptor.permissions, vec![DescriptorPermission::Readable]);
+    }
+
+    #[test]
+    fn user_description_encoding() {
+        let descriptor = Descriptor::user_description("Temp");
+        assert_eq!(descriptor.uuid, Uuid::from_sdp_short_uuid(0x2901_u16));
+        assert_eq!(descriptor.value, Some(b"Temp".to_vec()));
+        assert_eq!(descriptor.permissions, vec![DescriptorPermission::Readable]);
+    }
+
+    #[test]
+    fn presentation_format_encoding() {
+        let descriptor = Descriptor::presentation_format(
+            Format::SInt16,
+            -2,
+            Unit::Celsius,
+            BLUETOOTH_SIG_NAMESPACE,
+            0x0106,
+        );
+        assert_eq!(descriptor.uuid, Uuid::from_sdp_short_uuid(0x2904_u16));
+        // Format, exponent, unit and description are little endian, 7 bytes in total
+        assert_eq!(
+            descriptor.value,
+            Some(vec![0x0E, 0xFE, 0x2F, 0x27, 0x01, 0x06, 0x01])
+        );
+        assert!(descriptor.is_readable());
+        assert!(!descriptor.is_writeable());
+    }
+
+    #[test]
+    fn aggregate_format_encoding() {
+        let descriptor = Descriptor::aggregate_format(&[0x0012, 0x0105]);
+        assert_eq!(descriptor.uuid, Uuid::from_sdp_short_uuid(0x2905_u16));
+        // Handles are little endian, in the order the value is made of
+        assert_eq!(descriptor.value, Some(vec![0x12, 0x00, 0x05, 0x01]));
+        assert!(descriptor.is_readable());
+    }
+}
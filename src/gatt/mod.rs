@@ -1,5 +1,11 @@
 pub mod characteristic;
+pub mod client;
+pub mod database;
 pub mod descriptor;
+pub mod notification;
 pub mod peripheral_event;
+pub(crate) mod prepared_write;
+pub mod presentation_format;
 pub mod properties;
+pub(crate) mod read_cache;
 pub mod service;
@@ -0,0 +1,112 @@
+use crate::{Error, ErrorType};
+
+/// ATT MTU every central supports, assumed until a larger one was negotiated
+pub const DEFAULT_ATT_MTU: u16 = 23;
+
+/// Bits a central wrote to the Client Characteristic Configuration descriptor (0x2902),
+/// which the stack adds to every characteristic that notifies or indicates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClientConfiguration {
+    pub notify: bool,
+    pub indicate: bool,
+}
+
+impl ClientConfiguration {
+    pub const NOTIFY: Self = ClientConfiguration {
+        notify: true,
+        indicate: false,
+    };
+
+    pub const INDICATE: Self = ClientConfiguration {
+        notify: false,
+        indicate: true,
+    };
+
+    /// Value of the descriptor, bit 0 enables notifications and bit 1 indications
+    pub fn bits(self) -> u16 {
+        u16::from(self.notify) | u16::from(self.indicate) << 1
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        ClientConfiguration {
+            notify: bits & 0x01 != 0,
+            indicate: bits & 0x02 != 0,
+        }
+    }
+}
+
+/// How notifications and indications handle values which do not fit into a single
+/// packet, which carries at most MTU - 3 bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizePolicy {
+    /// Fail the update without sending anything
+    #[default]
+    Reject,
+    /// Send the value as consecutive packets of the largest size every subscriber accepts
+    Split,
+}
+
+impl OversizePolicy {
+    /// Cut `value` into the payloads to send to centrals with the given MTU
+    pub(crate) fn payloads(self, value: Vec<u8>, mtu: u16) -> Result<Vec<Vec<u8>>, Error> {
+        let max_len = usize::from(mtu.saturating_sub(3)).max(1);
+        if value.len() <= max_len {
+            return Ok(vec![value]);
+        }
+        match self {
+            OversizePolicy::Reject => Err(Error::new(
+                "ValueTooLong".to_string(),
+                format!(
+                    "Value of {} bytes exceeds the notification limit of {} bytes (MTU {})",
+                    value.len(),
+                    max_len,
+                    mtu
+                ),
+                ErrorType::Failed,
+            )),
+            OversizePolicy::Split => Ok(value.chunks(max_len).map(<[u8]>::to_vec).collect()),
+        }
+    }
+
+    /// Cut `value` for the smallest of the subscriber MTUs, `None` stands for a subscriber
+    /// whose MTU is unknown. Those are left out, the stack truncates what they cannot take
+    pub(crate) fn payloads_for_known(
+        self,
+        value: Vec<u8>,
+        mtus: impl IntoIterator<Item = Option<u16>>,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        match mtus.into_iter().flatten().min() {
+            Some(mtu) => self.payloads(value, mtu),
+            None => Ok(vec![value]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_mtus_are_not_checked() {
+        let value = vec![0; 100];
+        let payloads = OversizePolicy::Reject
+            .payloads_for_known(value.clone(), [None, None])
+            .unwrap();
+        assert_eq!(payloads, vec![value]);
+    }
+
+    #[test]
+    fn smallest_known_mtu_applies() {
+        let value = vec![0; 30];
+        assert!(OversizePolicy::Reject
+            .payloads_for_known(value.clone(), [None, Some(DEFAULT_ATT_MTU), Some(247)])
+            .is_err());
+        let payloads = OversizePolicy::Split
+            .payloads_for_known(value, [Some(247), None, Some(DEFAULT_ATT_MTU)])
+            .unwrap();
+        assert_eq!(
+            payloads.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![20, 10]
+        );
+    }
+}
@@ -1,6 +1,14 @@
+use super::{client::Client, notification::ClientConfiguration};
+use crate::AttError;
 use tokio::sync::oneshot;
 use uuid::Uuid;
 
+/// Value returned to a read request, or the ATT error to fail it with
+pub type ReadResponse = Result<Vec<u8>, AttError>;
+
+/// Result of a write request, `Err` carries the ATT error returned to the central
+pub type WriteResponse = Result<(), AttError>;
+
 #[derive(Debug)]
 pub enum PeripheralEvent {
     DidUpdateState {
@@ -13,26 +21,82 @@ pub enum PeripheralEvent {
         service: Uuid,
         error: Option<String>,
     },
+    /// Reported by BlueZ with the first request or subscription of a central, so centrals
+    /// which connect without using this GATT server are left out.
+    /// Never reported on macOS, CoreBluetooth has no connection callbacks for peripherals
+    DidConnect {
+        client: Client,
+    },
+    /// Only follows `DidConnect`, so it is never reported on macOS either.
+    /// `reason` is only set where the stack exposes it, BlueZ does not
+    DidDisconnect {
+        client: Client,
+        reason: Option<String>,
+    },
+    /// `configuration` tells whether the central enabled notifications or indications.
+    /// `client` is `None` if the stack does not tell which central subscribed, which is
+    /// the case for characteristics that only indicate on BlueZ with several centrals connected
     DidSubscribeToCharacteristic {
-        client: String,
+        client: Option<Client>,
         service: Uuid,
         characteristic: Uuid,
+        configuration: ClientConfiguration,
     },
+    /// `client` is `None` if it was unknown when the central subscribed. Also sent for
+    /// every subscription which ends on disconnect, or on BlueZ in `stop_advertising`,
+    /// which unregisters the GATT application
     DidUnsubscribeFromCharacteristic {
-        client: String,
+        client: Option<Client>,
         service: Uuid,
         characteristic: Uuid,
     },
+    /// Respond with the complete value, the part starting at `offset` is returned to
+    /// the central. Follow-up reads of a long value are answered without another event
     DidReceiveReadRequest {
-        client: String,
+        client: Client,
         service: Uuid,
         characteristic: Uuid,
-        responder: oneshot::Sender<Vec<u8>>,
+        offset: u16,
+        responder: oneshot::Sender<ReadResponse>,
     },
+    /// Long writes are reassembled, `value` is always the complete value.
+    /// BlueZ does not mark the last chunk of a long write, which is recognised by being
+    /// shorter than the others. If the length is a multiple of the chunk size, the central
+    /// was answered before the event is reported and the response has no effect
     DidReceiveWriteRequest {
-        client: String,
+        client: Client,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+        responder: oneshot::Sender<WriteResponse>,
+    },
+    /// Read of a descriptor without a stored value, respond with the complete value
+    /// and the part starting at `offset` is returned to the central.
+    /// Only reported by BlueZ, CoreBluetooth only serves stored descriptor values
+    DidReceiveDescriptorReadRequest {
+        client: Client,
+        service: Uuid,
+        characteristic: Uuid,
+        descriptor: Uuid,
+        offset: u16,
+        responder: oneshot::Sender<ReadResponse>,
+    },
+    /// `value` is written starting at `offset`, chunks of a long write are not reassembled.
+    /// Only reported by BlueZ
+    DidReceiveDescriptorWriteRequest {
+        client: Client,
         service: Uuid,
         characteristic: Uuid,
+        descriptor: Uuid,
+        offset: u16,
         value: Vec<u8>,
+        responder: oneshot::Sender<WriteResponse>,
+    },
+    /// A long write was abandoned before it completed, no write event follows for it.
+    /// Not reported by CoreBluetooth, which discards cancelled writes itself
+    DidCancelPreparedWrite {
+        client: Client,
+        service: Uuid,
+        characteristic: Uuid,
     },
 }
@@ -0,0 +1,136 @@
+use crate::AttError;
+// The queue is only used by backends which receive it as a whole at Execute Write
+#[cfg(any(target_os = "macos", target_os = "ios", feature = "mock", test))]
+use uuid::Uuid;
+
+/// Chunk of a queued write, addressed by service and characteristic uuid
+#[cfg(any(target_os = "macos", target_os = "ios", feature = "mock", test))]
+#[derive(Debug)]
+pub(crate) struct PreparedWrite {
+    pub(crate) service: Uuid,
+    pub(crate) characteristic: Uuid,
+    pub(crate) offset: u16,
+    pub(crate) value: Vec<u8>,
+}
+
+/// Complete value of one characteristic after an Execute Write
+#[cfg(any(target_os = "macos", target_os = "ios", feature = "mock", test))]
+#[derive(Debug)]
+pub(crate) struct ExecutedWrite {
+    pub(crate) service: Uuid,
+    pub(crate) characteristic: Uuid,
+    pub(crate) value: Result<Vec<u8>, AttError>,
+}
+
+/// Place `chunk` at `offset`, which may overwrite earlier chunks but not leave a gap
+pub(crate) fn write_at(value: &mut Vec<u8>, offset: u16, chunk: &[u8]) -> Result<(), AttError> {
+    let offset = usize::from(offset);
+    if offset > value.len() {
+        return Err(AttError::InvalidOffset);
+    }
+    let end = offset + chunk.len();
+    if end > value.len() {
+        value.resize(end, 0);
+    }
+    value[offset..end].copy_from_slice(chunk);
+    Ok(())
+}
+
+/// Merge the queued chunks into one value per characteristic,
+/// in the order the characteristics were first written
+#[cfg(any(target_os = "macos", target_os = "ios", feature = "mock", test))]
+pub(crate) fn execute(queue: Vec<PreparedWrite>) -> Vec<ExecutedWrite> {
+    let mut executed: Vec<ExecutedWrite> = vec![];
+    for chunk in queue {
+        let index = match executed
+            .iter()
+            .position(|w| w.service == chunk.service && w.characteristic == chunk.characteristic)
+        {
+            Some(index) => index,
+            None => {
+                executed.push(ExecutedWrite {
+                    service: chunk.service,
+                    characteristic: chunk.characteristic,
+                    value: Ok(vec![]),
+                });
+                executed.len() - 1
+            }
+        };
+        if let Ok(value) = &mut executed[index].value {
+            if let Err(error) = write_at(value, chunk.offset, &chunk.value) {
+                executed[index].value = Err(error);
+            }
+        }
+    }
+    executed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE: Uuid = Uuid::from_u128(1);
+
+    fn chunk(characteristic: u128, offset: u16, value: &[u8]) -> PreparedWrite {
+        PreparedWrite {
+            service: SERVICE,
+            characteristic: Uuid::from_u128(characteristic),
+            offset,
+            value: value.to_vec(),
+        }
+    }
+
+    #[test]
+    fn write_at_appends_and_overwrites() {
+        let mut value = vec![];
+        write_at(&mut value, 0, &[1, 2, 3]).unwrap();
+        write_at(&mut value, 3, &[4, 5]).unwrap();
+        assert_eq!(value, vec![1, 2, 3, 4, 5]);
+
+        // An overlapping chunk replaces the bytes it covers
+        write_at(&mut value, 1, &[9, 9]).unwrap();
+        assert_eq!(value, vec![1, 9, 9, 4, 5]);
+        write_at(&mut value, 4, &[7, 8]).unwrap();
+        assert_eq!(value, vec![1, 9, 9, 4, 7, 8]);
+    }
+
+    #[test]
+    fn write_at_rejects_a_gap() {
+        let mut value = vec![1, 2];
+        assert_eq!(write_at(&mut value, 3, &[4]), Err(AttError::InvalidOffset));
+        assert_eq!(value, vec![1, 2]);
+        assert_eq!(write_at(&mut vec![], 1, &[1]), Err(AttError::InvalidOffset));
+    }
+
+    #[test]
+    fn execute_merges_per_characteristic() {
+        let executed = execute(vec![
+            chunk(2, 0, &[1, 2]),
+            chunk(3, 0, &[7]),
+            chunk(2, 2, &[3]),
+            chunk(2, 1, &[5]),
+        ]);
+        assert_eq!(executed.len(), 2);
+        assert_eq!(executed[0].characteristic, Uuid::from_u128(2));
+        assert_eq!(executed[0].value, Ok(vec![1, 5, 3]));
+        assert_eq!(executed[1].characteristic, Uuid::from_u128(3));
+        assert_eq!(executed[1].value, Ok(vec![7]));
+    }
+
+    #[test]
+    fn execute_fails_a_characteristic_with_a_gap() {
+        let executed = execute(vec![
+            chunk(2, 0, &[1]),
+            chunk(2, 5, &[2]),
+            chunk(2, 1, &[3]),
+            chunk(3, 0, &[4]),
+        ]);
+        assert_eq!(executed[0].value, Err(AttError::InvalidOffset));
+        assert_eq!(executed[1].value, Ok(vec![4]));
+    }
+
+    #[test]
+    fn execute_empty_queue() {
+        assert!(execute(vec![]).is_empty());
+    }
+}
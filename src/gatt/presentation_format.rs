@@ -0,0 +1,175 @@
+/// Namespace of the SIG assigned description values
+pub const BLUETOOTH_SIG_NAMESPACE: u8 = 0x01;
+
+/// Description value when the characteristic is not one of several with the same format
+pub const DESCRIPTION_UNKNOWN: u16 = 0x0000;
+
+/// Format of a characteristic value, as assigned by the Bluetooth SIG
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    Boolean = 0x01,
+    UInt2 = 0x02,
+    UInt4 = 0x03,
+    UInt8 = 0x04,
+    UInt12 = 0x05,
+    UInt16 = 0x06,
+    UInt24 = 0x07,
+    UInt32 = 0x08,
+    UInt48 = 0x09,
+    UInt64 = 0x0A,
+    UInt128 = 0x0B,
+    SInt8 = 0x0C,
+    SInt12 = 0x0D,
+    SInt16 = 0x0E,
+    SInt24 = 0x0F,
+    SInt32 = 0x10,
+    SInt48 = 0x11,
+    SInt64 = 0x12,
+    SInt128 = 0x13,
+    Float32 = 0x14,
+    Float64 = 0x15,
+    /// IEEE 11073 16-bit SFLOAT
+    MedFloat16 = 0x16,
+    /// IEEE 11073 32-bit FLOAT
+    MedFloat32 = 0x17,
+    /// Two UInt16 values
+    DUInt16 = 0x18,
+    Utf8 = 0x19,
+    Utf16 = 0x1A,
+    Struct = 0x1B,
+    MedAsn1 = 0x1C,
+}
+
+/// Unit of a characteristic value, as assigned by the Bluetooth SIG
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    Unitless = 0x2700,
+    Metre = 0x2701,
+    Kilogram = 0x2702,
+    Second = 0x2703,
+    Ampere = 0x2704,
+    Kelvin = 0x2705,
+    Mole = 0x2706,
+    Candela = 0x2707,
+    SquareMetre = 0x2710,
+    CubicMetre = 0x2711,
+    MetrePerSecond = 0x2712,
+    MetrePerSecondSquared = 0x2713,
+    ReciprocalMetre = 0x2714,
+    KilogramPerCubicMetre = 0x2715,
+    KilogramPerSquareMetre = 0x2716,
+    CubicMetrePerKilogram = 0x2717,
+    AmperePerSquareMetre = 0x2718,
+    AmperePerMetre = 0x2719,
+    MolePerCubicMetre = 0x271A,
+    /// Mass concentration, in kilogram per cubic metre
+    MassConcentration = 0x271B,
+    CandelaPerSquareMetre = 0x271C,
+    RefractiveIndex = 0x271D,
+    RelativePermeability = 0x271E,
+    Radian = 0x2720,
+    Steradian = 0x2721,
+    Hertz = 0x2722,
+    Newton = 0x2723,
+    Pascal = 0x2724,
+    Joule = 0x2725,
+    Watt = 0x2726,
+    Coulomb = 0x2727,
+    Volt = 0x2728,
+    Farad = 0x2729,
+    Ohm = 0x272A,
+    Siemens = 0x272B,
+    Weber = 0x272C,
+    Tesla = 0x272D,
+    Henry = 0x272E,
+    Celsius = 0x272F,
+    Lumen = 0x2730,
+    Lux = 0x2731,
+    Becquerel = 0x2732,
+    Gray = 0x2733,
+    Sievert = 0x2734,
+    Katal = 0x2735,
+    PascalSecond = 0x2740,
+    NewtonMetre = 0x2741,
+    NewtonPerMetre = 0x2742,
+    RadianPerSecond = 0x2743,
+    RadianPerSecondSquared = 0x2744,
+    WattPerSquareMetre = 0x2745,
+    JoulePerKelvin = 0x2746,
+    JoulePerKilogramKelvin = 0x2747,
+    JoulePerKilogram = 0x2748,
+    WattPerMetreKelvin = 0x2749,
+    JoulePerCubicMetre = 0x274A,
+    VoltPerMetre = 0x274B,
+    CoulombPerCubicMetre = 0x274C,
+    /// Surface charge density, in coulomb per square metre
+    CoulombPerSquareMetre = 0x274D,
+    /// Electric flux density, in coulomb per square metre
+    ElectricFluxDensity = 0x274E,
+    FaradPerMetre = 0x274F,
+    HenryPerMetre = 0x2750,
+    JoulePerMole = 0x2751,
+    JoulePerMoleKelvin = 0x2752,
+    CoulombPerKilogram = 0x2753,
+    GrayPerSecond = 0x2754,
+    WattPerSteradian = 0x2755,
+    WattPerSquareMetreSteradian = 0x2756,
+    KatalPerCubicMetre = 0x2757,
+    Minute = 0x2760,
+    Hour = 0x2761,
+    Day = 0x2762,
+    Degree = 0x2763,
+    ArcMinute = 0x2764,
+    ArcSecond = 0x2765,
+    Hectare = 0x2766,
+    Litre = 0x2767,
+    Tonne = 0x2768,
+    Bar = 0x2780,
+    MillimetreOfMercury = 0x2781,
+    Angstrom = 0x2782,
+    NauticalMile = 0x2783,
+    Barn = 0x2784,
+    Knot = 0x2785,
+    Neper = 0x2786,
+    Bel = 0x2787,
+    Yard = 0x27A0,
+    Parsec = 0x27A1,
+    Inch = 0x27A2,
+    Foot = 0x27A3,
+    Mile = 0x27A4,
+    PoundForcePerSquareInch = 0x27A5,
+    KilometrePerHour = 0x27A6,
+    MilePerHour = 0x27A7,
+    RevolutionPerMinute = 0x27A8,
+    GramCalorie = 0x27A9,
+    KilogramCalorie = 0x27AA,
+    KilowattHour = 0x27AB,
+    Fahrenheit = 0x27AC,
+    Percentage = 0x27AD,
+    PerMille = 0x27AE,
+    BeatsPerMinute = 0x27AF,
+    AmpereHour = 0x27B0,
+    MilligramPerDecilitre = 0x27B1,
+    MillimolePerLitre = 0x27B2,
+    Year = 0x27B3,
+    Month = 0x27B4,
+    CountPerCubicMetre = 0x27B5,
+    /// Irradiance, in watt per square metre
+    Irradiance = 0x27B6,
+    MillilitrePerKilogramPerMinute = 0x27B7,
+    Pound = 0x27B8,
+    MetabolicEquivalent = 0x27B9,
+    StepPerMinute = 0x27BA,
+    StrokePerMinute = 0x27BC,
+    KilometrePerMinute = 0x27BD,
+    LumenPerWatt = 0x27BE,
+    LumenHour = 0x27BF,
+    LuxHour = 0x27C0,
+    GramPerSecond = 0x27C1,
+    LitrePerSecond = 0x27C2,
+    Decibel = 0x27C3,
+    PartsPerMillion = 0x27C4,
+    PartsPerBillion = 0x27C5,
+}
@@ -19,3 +19,17 @@ pub enum AttributePermission {
     ReadEncryptionRequired,
     WriteEncryptionRequired,
 }
+
+/// Access centrals have to a descriptor, which unlike a characteristic
+/// can only be read or written
+#[derive(Debug, Clone, PartialEq)]
+pub enum DescriptorPermission {
+    Readable,
+    Writeable,
+    ReadEncryptionRequired,
+    WriteEncryptionRequired,
+    ReadAuthenticationRequired,
+    WriteAuthenticationRequired,
+    /// Every access has to be authorized on the peripheral first
+    AuthorizationRequired,
+}
@@ -0,0 +1,232 @@
+use super::{client::Client, peripheral_event::ReadResponse};
+use crate::AttError;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+type Key = (String, Uuid, Uuid);
+
+/// A central continues a long read right away, a value it did not
+/// read on for longer than an ATT transaction may take is stale
+const EXPIRY: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct Entry {
+    value: Vec<u8>,
+    last_read: Instant,
+}
+
+/// Values handed out by the application, kept while a central reads them in
+/// consecutive Read Blob requests so every chunk comes from the same value
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ReadCache {
+    values: Arc<Mutex<HashMap<Key, Entry>>>,
+}
+
+impl ReadCache {
+    /// Continue a Read Blob sequence from the cached value,
+    /// `None` when the application has to be asked for the value
+    pub(crate) fn read(
+        &self,
+        client: &Client,
+        service: Uuid,
+        characteristic: Uuid,
+        offset: u16,
+    ) -> Option<ReadResponse> {
+        self.read_at(client, service, characteristic, offset, Instant::now())
+    }
+
+    fn read_at(
+        &self,
+        client: &Client,
+        service: Uuid,
+        characteristic: Uuid,
+        offset: u16,
+        now: Instant,
+    ) -> Option<ReadResponse> {
+        let key = (client.id.clone(), service, characteristic);
+        let mut values = self.values.lock().unwrap();
+        // A read at offset 0 always starts a new sequence
+        if offset == 0 {
+            values.remove(&key);
+            return None;
+        }
+        let entry = values.get_mut(&key)?;
+        if now.duration_since(entry.last_read) >= EXPIRY {
+            values.remove(&key);
+            return None;
+        }
+        entry.last_read = now;
+        let response = slice(&entry.value, offset);
+        if !is_pending(&response, client.mtu) {
+            values.remove(&key);
+        }
+        Some(response)
+    }
+
+    /// Slice the application's value at `offset`, keeping it for the following
+    /// Read Blob requests if it does not fit into a single response
+    pub(crate) fn store(
+        &self,
+        client: &Client,
+        service: Uuid,
+        characteristic: Uuid,
+        offset: u16,
+        value: Vec<u8>,
+    ) -> ReadResponse {
+        let key = (client.id.clone(), service, characteristic);
+        let response = slice(&value, offset);
+        let mut values = self.values.lock().unwrap();
+        if is_pending(&response, client.mtu) {
+            values.insert(
+                key,
+                Entry {
+                    value,
+                    last_read: Instant::now(),
+                },
+            );
+        } else {
+            values.remove(&key);
+        }
+        response
+    }
+
+    pub(crate) fn forget(&self, client: &Client) {
+        self.values
+            .lock()
+            .unwrap()
+            .retain(|(id, _, _), _| *id != client.id);
+    }
+}
+
+/// Part of the value a read at `offset` returns
+pub(crate) fn slice(value: &[u8], offset: u16) -> ReadResponse {
+    value
+        .get(usize::from(offset)..)
+        .map(<[u8]>::to_vec)
+        .ok_or(AttError::InvalidOffset)
+}
+
+/// A response which fills a whole packet (MTU - 1 bytes) is followed by another Read Blob.
+/// With an unknown MTU the value is kept until a read reaches its end or it expires
+fn is_pending(response: &ReadResponse, mtu: Option<u16>) -> bool {
+    match (response, mtu) {
+        (Ok(rest), Some(mtu)) => rest.len() >= usize::from(mtu.saturating_sub(1)),
+        (Ok(rest), None) => !rest.is_empty(),
+        (Err(_), _) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE: Uuid = Uuid::from_u128(1);
+    const CHARACTERISTIC: Uuid = Uuid::from_u128(2);
+
+    fn client(mtu: Option<u16>) -> Client {
+        let mut client = Client::new("central");
+        client.mtu = mtu;
+        client
+    }
+
+    #[test]
+    fn slice_at_offsets() {
+        let value = [1, 2, 3];
+        assert_eq!(slice(&value, 0), Ok(vec![1, 2, 3]));
+        assert_eq!(slice(&value, 1), Ok(vec![2, 3]));
+        assert_eq!(slice(&value, 3), Ok(vec![]));
+        assert_eq!(slice(&value, 4), Err(AttError::InvalidOffset));
+    }
+
+    #[test]
+    fn long_value_is_served_until_read_to_the_end() {
+        let reads = ReadCache::default();
+        let client = client(Some(23));
+        let value: Vec<u8> = (0..30).collect();
+
+        let first = reads.store(&client, SERVICE, CHARACTERISTIC, 0, value.clone());
+        assert_eq!(first, Ok(value.clone()));
+        let rest = reads.read(&client, SERVICE, CHARACTERISTIC, 22);
+        assert_eq!(rest, Some(Ok(value[22..].to_vec())));
+        // The last chunk was shorter than a packet, the next blob asks the application again
+        assert_eq!(reads.read(&client, SERVICE, CHARACTERISTIC, 22), None);
+    }
+
+    #[test]
+    fn new_sequence_replaces_the_value() {
+        let reads = ReadCache::default();
+        let client = client(Some(23));
+        let old: Vec<u8> = vec![1; 30];
+        assert!(reads
+            .store(&client, SERVICE, CHARACTERISTIC, 0, old)
+            .is_ok());
+
+        // A read at offset 0 drops the old value even before the new one is stored
+        assert_eq!(reads.read(&client, SERVICE, CHARACTERISTIC, 0), None);
+        assert_eq!(reads.read(&client, SERVICE, CHARACTERISTIC, 22), None);
+
+        let new: Vec<u8> = vec![2; 30];
+        assert!(reads
+            .store(&client, SERVICE, CHARACTERISTIC, 0, new.clone())
+            .is_ok());
+        assert_eq!(
+            reads.read(&client, SERVICE, CHARACTERISTIC, 22),
+            Some(Ok(new[22..].to_vec()))
+        );
+    }
+
+    #[test]
+    fn unknown_mtu_keeps_the_value_until_its_end() {
+        let reads = ReadCache::default();
+        let client = client(None);
+        let value: Vec<u8> = (0..10).collect();
+
+        assert!(reads
+            .store(&client, SERVICE, CHARACTERISTIC, 0, value.clone())
+            .is_ok());
+        assert_eq!(
+            reads.read(&client, SERVICE, CHARACTERISTIC, 5),
+            Some(Ok(value[5..].to_vec()))
+        );
+        assert_eq!(
+            reads.read(&client, SERVICE, CHARACTERISTIC, 10),
+            Some(Ok(vec![]))
+        );
+        assert_eq!(reads.read(&client, SERVICE, CHARACTERISTIC, 5), None);
+    }
+
+    #[test]
+    fn offset_past_the_end_ends_the_sequence() {
+        let reads = ReadCache::default();
+        let client = client(None);
+        assert!(reads
+            .store(&client, SERVICE, CHARACTERISTIC, 0, vec![0; 10])
+            .is_ok());
+
+        assert_eq!(
+            reads.read(&client, SERVICE, CHARACTERISTIC, 11),
+            Some(Err(AttError::InvalidOffset))
+        );
+        assert_eq!(reads.read(&client, SERVICE, CHARACTERISTIC, 5), None);
+    }
+
+    #[test]
+    fn value_expires() {
+        let reads = ReadCache::default();
+        let client = client(None);
+        assert!(reads
+            .store(&client, SERVICE, CHARACTERISTIC, 0, vec![0; 10])
+            .is_ok());
+
+        let later = Instant::now() + EXPIRY;
+        assert_eq!(
+            reads.read_at(&client, SERVICE, CHARACTERISTIC, 5, later),
+            None
+        );
+        assert_eq!(reads.read(&client, SERVICE, CHARACTERISTIC, 5), None);
+    }
+}
@@ -1,4 +1,5 @@
 use super::characteristic::Characteristic;
+use crate::{Error, ErrorType, SdpShortUuid};
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
@@ -8,6 +9,34 @@ pub struct Service {
     pub characteristics: Vec<Characteristic>,
 }
 
+impl Service {
+    /// Reject descriptors the stack manages itself, the Client (0x2902) and
+    /// Server (0x2903) Characteristic Configuration, which would conflict with its own
+    pub(crate) fn check_descriptors(&self) -> Result<(), Error> {
+        let reserved = [
+            Uuid::from_sdp_short_uuid(0x2902_u16),
+            Uuid::from_sdp_short_uuid(0x2903_u16),
+        ];
+        for characteristic in &self.characteristics {
+            if let Some(descriptor) = characteristic
+                .descriptors
+                .iter()
+                .find(|descriptor| reserved.contains(&descriptor.uuid))
+            {
+                return Err(Error::new(
+                    "ReservedDescriptor".to_string(),
+                    format!(
+                        "Descriptor {} of characteristic {} is managed by the stack, remove it from the service",
+                        descriptor.uuid, characteristic.uuid
+                    ),
+                    ErrorType::Failed,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Default for Service {
     fn default() -> Self {
         Service {
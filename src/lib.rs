@@ -1,6 +1,14 @@
+pub mod adv;
 mod error;
 pub mod gatt;
 mod peripheral;
 mod uuid;
 
-pub use self::{error::*, peripheral::Peripheral, uuid::*};
+pub use self::{
+    error::*,
+    peripheral::{Peripheral, PeripheralBackend},
+    uuid::*,
+};
+
+#[cfg(feature = "mock")]
+pub use self::peripheral::mock;
@@ -0,0 +1,43 @@
+use crate::{
+    adv::advertisement_data::{AdvertisementData, LocalName},
+    Error, ErrorType,
+};
+use bluer::adv::{Advertisement, Feature};
+use std::collections::BTreeSet;
+
+pub fn parse_advertisement(data: &AdvertisementData) -> Result<Advertisement, Error> {
+    // BlueZ truncates a name which does not fit and marks it as shortened itself
+    let local_name = match &data.local_name {
+        Some(LocalName::Complete(name)) => Some(name.clone()),
+        Some(LocalName::Shortened(_)) => {
+            return Err(Error::new(
+                "NotSupported",
+                "BlueZ shortens the local name itself, pass the complete name",
+                ErrorType::Bluez,
+            ))
+        }
+        None => None,
+    };
+
+    let mut system_includes = BTreeSet::new();
+    if data.include_tx_power {
+        system_includes.insert(Feature::TxPower);
+    }
+
+    Ok(Advertisement {
+        service_uuids: data.service_uuids.iter().copied().collect(),
+        solicit_uuids: data.solicited_uuids.iter().copied().collect(),
+        service_data: data.service_data.clone(),
+        manufacturer_data: data.manufacturer_data.clone(),
+        appearance: data.appearance,
+        system_includes,
+        discoverable: Some(data.discoverable),
+        local_name,
+        min_interval: data.min_interval,
+        max_interval: data.max_interval,
+        tx_power: data.tx_power,
+        duration: data.duration,
+        timeout: data.timeout,
+        ..Default::default()
+    })
+}
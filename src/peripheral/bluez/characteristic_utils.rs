@@ -1,20 +1,48 @@
-use crate::gatt::characteristic::{self, Secure, Write};
-use crate::gatt::peripheral_event::PeripheralEvent;
+use crate::gatt::characteristic;
+use crate::gatt::client::Client;
+use crate::gatt::database::GattDatabase;
+use crate::gatt::notification::ClientConfiguration;
+use crate::gatt::peripheral_event::{PeripheralEvent, ReadResponse, WriteResponse};
+use crate::gatt::properties::{AttributePermission, CharacteristicProperty, DescriptorPermission};
+use crate::gatt::read_cache::{slice, ReadCache};
 use crate::gatt::{descriptor, service};
+use crate::{AttError, Error, ErrorType, SdpShortUuid};
 use bluer::gatt::local::{
-    service_control, Characteristic, CharacteristicNotifier, CharacteristicNotify,
-    CharacteristicNotifyMethod, CharacteristicWrite, CharacteristicWriteMethod,
-    CharacteristicWriteRequest, Descriptor, ReqError, Service,
+    characteristic_control, service_control, Characteristic, CharacteristicControl,
+    CharacteristicControlEvent, CharacteristicControlHandle, CharacteristicNotifier,
+    CharacteristicNotify, CharacteristicNotifyMethod, CharacteristicWrite,
+    CharacteristicWriteMethod, CharacteristicWriteRequest, Descriptor, DescriptorRead,
+    DescriptorReadRequest, DescriptorWrite, DescriptorWriteRequest, ReqError, Service,
 };
 use bluer::gatt::local::{CharacteristicRead, CharacteristicReadRequest};
-use futures::FutureExt;
-use tokio::sync::mpsc::Sender;
+use bluer::gatt::{CharacteristicWriter, WriteOp};
+
+use super::{
+    clients::Clients,
+    connections::on_activity,
+    notifiers::Notifiers,
+    prepared_writes::{Chunk, PreparedWrites},
+};
+use futures::{FutureExt, StreamExt};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::{mpsc::Sender, oneshot};
 use uuid::Uuid;
 
-pub fn parse_services(
-    gatt_services: Vec<service::Service>,
-    sender_tx: Sender<PeripheralEvent>,
-) -> Vec<Service> {
+/// BlueZ hands over the next queued chunk as soon as the previous one was answered
+const PREPARED_WRITE_IDLE: Duration = Duration::from_millis(500);
+
+/// State shared by the callbacks of every registered characteristic
+#[derive(Debug, Clone)]
+pub struct GattContext {
+    pub sender_tx: Sender<PeripheralEvent>,
+    pub database: GattDatabase,
+    pub notifiers: Notifiers,
+    pub clients: Clients,
+    pub reads: ReadCache,
+    pub prepared_writes: PreparedWrites,
+}
+
+pub fn parse_services(gatt_services: Vec<service::Service>, context: GattContext) -> Vec<Service> {
     let mut services: Vec<Service> = vec![];
 
     for service in gatt_services.iter() {
@@ -23,7 +51,7 @@ pub fn parse_services(
         let chars: Vec<Characteristic> = service
             .characteristics
             .iter()
-            .map(|data| parse_characteristic(data.clone(), service.uuid, sender_tx.clone()))
+            .map(|data| parse_characteristic(data.clone(), service.uuid, context.clone()))
             .collect();
 
         let service = Service {
@@ -39,34 +67,129 @@ pub fn parse_services(
     services
 }
 
+/// Reject properties BlueZ would publish without their security requirement,
+/// bluer sets no flag which makes notifications or indications need an encrypted link
+pub fn check_properties(service: &service::Service) -> Result<(), Error> {
+    for characteristic in &service.characteristics {
+        for property in [
+            CharacteristicProperty::NotifyEncryptionRequired,
+            CharacteristicProperty::IndicateEncryptionRequired,
+        ] {
+            if characteristic.properties.contains(&property) {
+                return Err(Error::new(
+                    "UnsupportedProperty".to_string(),
+                    format!(
+                        "{:?} of characteristic {} cannot be enforced by BlueZ, \
+                         values would be sent over unencrypted links",
+                        property, characteristic.uuid
+                    ),
+                    ErrorType::Bluez,
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// BlueZ publishes Characteristic Extended Properties (0x2900) itself from the reliable
+/// write and writable auxiliaries flags, a declared descriptor is turned into those flags.
+/// Reject one without its two byte value, or setting reliable write on a characteristic
+/// which cannot be written
+pub fn check_descriptors(service: &service::Service) -> Result<(), Error> {
+    for characteristic in &service.characteristics {
+        let Some(descriptor) = extended_properties(characteristic) else {
+            continue;
+        };
+        let reason = match &descriptor.value {
+            Some(value) if value.len() == 2 => {
+                let (reliable_write, _) = extended_flags(descriptor);
+                (reliable_write
+                    && !characteristic
+                        .properties
+                        .contains(&CharacteristicProperty::Write))
+                .then_some("reliable write needs the Write property")
+            }
+            _ => Some("it needs a value of two bytes"),
+        };
+        if let Some(reason) = reason {
+            return Err(Error::new(
+                "UnsupportedDescriptor".to_string(),
+                format!(
+                    "Extended properties of characteristic {} cannot be published, {}",
+                    characteristic.uuid, reason
+                ),
+                ErrorType::Bluez,
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn extended_properties(
+    characteristic: &characteristic::Characteristic,
+) -> Option<&descriptor::Descriptor> {
+    let uuid = Uuid::from_sdp_short_uuid(0x2900_u16);
+    characteristic
+        .descriptors
+        .iter()
+        .find(|descriptor| descriptor.uuid == uuid)
+}
+
+/// Reliable write and writable auxiliaries bits of an extended properties descriptor
+fn extended_flags(descriptor: &descriptor::Descriptor) -> (bool, bool) {
+    let flags = descriptor
+        .value
+        .as_ref()
+        .and_then(|value| value.first().copied())
+        .unwrap_or_default();
+    (flags & 0x01 != 0, flags & 0x02 != 0)
+}
+
+/// BlueZ only reports a Handle Value Confirmation for characteristics which indicate
+/// without also notifying, for others it sends the value as a notification
+pub fn check_confirmable(characteristic: &characteristic::Characteristic) -> Result<(), Error> {
+    let properties = &characteristic.properties;
+    if !properties.contains(&CharacteristicProperty::Indicate) {
+        return Err(Error::new(
+            "NotSupported",
+            "Characteristic does not support indications",
+            ErrorType::Bluez,
+        ));
+    }
+    if properties.contains(&CharacteristicProperty::Notify) {
+        return Err(Error::new(
+            "IndicationNotConfirmable",
+            "BlueZ cannot confirm indications of a characteristic which also notifies",
+            ErrorType::Bluez,
+        ));
+    }
+    Ok(())
+}
+
 fn parse_characteristic(
     characteristic: characteristic::Characteristic,
     service_uuid: Uuid,
-    sender_tx: Sender<PeripheralEvent>,
+    context: GattContext,
 ) -> Characteristic {
     let mut char_read: Option<CharacteristicRead> = None;
     let mut char_write: Option<CharacteristicWrite> = None;
     let mut char_notify: Option<CharacteristicNotify> = None;
 
-    let read_sender = sender_tx.clone();
-    if let Some(secure) = &characteristic.properties.read {
-        let is_secure = match secure.0 {
-            Secure::Secure(_) => true,
-            Secure::Insecure(_) => false,
-        };
+    let properties = &characteristic.properties;
+    let permissions = &characteristic.permissions;
+    let (reliable_write, writable_auxiliaries) = extended_properties(&characteristic)
+        .map(extended_flags)
+        .unwrap_or_default();
+
+    let read_context = context.clone();
+    if properties.contains(&CharacteristicProperty::Read) {
         char_read = Some(CharacteristicRead {
             read: true,
-            secure_read: is_secure,
+            encrypt_read: permissions.contains(&AttributePermission::ReadEncryptionRequired),
             fun: Box::new(move |request: CharacteristicReadRequest| {
-                let sender_tx_clone = read_sender.clone();
+                let context_clone = read_context.clone();
                 async move {
-                    return on_read_request(
-                        sender_tx_clone,
-                        request,
-                        service_uuid,
-                        characteristic.uuid,
-                    )
-                    .await;
+                    on_read_request(context_clone, request, service_uuid, characteristic.uuid).await
                 }
                 .boxed()
             }),
@@ -74,35 +197,30 @@ fn parse_characteristic(
         })
     }
 
-    let write_sender = sender_tx.clone();
-    if let Some(write) = &characteristic.properties.write {
-        let mut is_secure = false;
-        let with_response = match write {
-            Write::WithResponse(secure) => {
-                is_secure = match secure {
-                    Secure::Secure(_) => true,
-                    Secure::Insecure(_) => false,
-                };
-                true
-            }
-            Write::WithoutResponse(_) => false,
-        };
+    let write_context = context.clone();
+    let write = properties.contains(&CharacteristicProperty::Write);
+    let write_without_response = properties.contains(&CharacteristicProperty::WriteWithoutResponse);
+    let authenticated_signed_writes =
+        properties.contains(&CharacteristicProperty::AuthenticatedSignedWrites);
+    if write || write_without_response || authenticated_signed_writes {
         char_write = Some(CharacteristicWrite {
-            write: !with_response,
-            write_without_response: with_response,
-            secure_write: is_secure,
+            write,
+            write_without_response,
+            reliable_write,
+            authenticated_signed_writes,
+            encrypt_write: permissions.contains(&AttributePermission::WriteEncryptionRequired),
             method: CharacteristicWriteMethod::Fun(Box::new(
                 move |value: Vec<u8>, request: CharacteristicWriteRequest| {
-                    let sender_tx_clone = write_sender.clone();
+                    let context_clone = write_context.clone();
                     async move {
-                        return on_write_request(
-                            sender_tx_clone,
+                        on_write_request(
+                            context_clone,
                             request,
                             service_uuid,
                             characteristic.uuid,
                             value,
                         )
-                        .await;
+                        .await
                     }
                     .boxed()
                 },
@@ -111,21 +229,39 @@ fn parse_characteristic(
         });
     }
 
-    let notify_sender = sender_tx.clone();
-    if characteristic.properties.notify.is_some() {
+    // Encryption-required notifications and indications are rejected by `check_properties`
+    let notify = properties.contains(&CharacteristicProperty::Notify);
+    let indicate = properties.contains(&CharacteristicProperty::Indicate);
+
+    let notify_context = context.clone();
+    let mut control_handle = CharacteristicControlHandle::default();
+    if notify {
+        // AcquireNotify names the central and its MTU, StartNotify passes neither
+        let (control, handle) = characteristic_control();
+        control_handle = handle;
+        tokio::spawn(on_char_control(
+            notify_context,
+            control,
+            service_uuid,
+            characteristic.uuid,
+        ));
         char_notify = Some(CharacteristicNotify {
-            notify: true,
+            notify,
+            indicate,
+            method: CharacteristicNotifyMethod::Io,
+            ..Default::default()
+        });
+    } else if indicate {
+        // Indications are only confirmed to the application through StartNotify
+        char_notify = Some(CharacteristicNotify {
+            notify,
+            indicate,
             method: CharacteristicNotifyMethod::Fun(Box::new(
                 move |notifier: CharacteristicNotifier| {
-                    let sender_tx_clone = notify_sender.clone();
+                    let context_clone = notify_context.clone();
                     async move {
-                        return on_char_notify(
-                            sender_tx_clone,
-                            notifier,
-                            service_uuid,
-                            characteristic.uuid,
-                        )
-                        .await;
+                        on_char_notify(context_clone, notifier, service_uuid, characteristic.uuid)
+                            .await
                     }
                     .boxed()
                 },
@@ -134,109 +270,591 @@ fn parse_characteristic(
         });
     }
 
-    if characteristic.properties.indicate.is_some() {
-        char_notify = Some(CharacteristicNotify {
-            indicate: true,
-            method: CharacteristicNotifyMethod::Io,
-            ..Default::default()
-        });
-    }
-
+    // BlueZ adds the extended properties descriptor from the flags
+    let extended = extended_properties(&characteristic).map(|descriptor| descriptor.uuid);
     let descriptors: Vec<Descriptor> = characteristic
         .descriptors
         .iter()
-        .map(|data| parse_descriptor(data.clone()))
+        .filter(|data| Some(data.uuid) != extended)
+        .map(|data| {
+            parse_descriptor(
+                data.clone(),
+                service_uuid,
+                characteristic.uuid,
+                context.clone(),
+            )
+        })
         .collect();
 
-    return Characteristic {
+    Characteristic {
         uuid: characteristic.uuid,
         read: char_read,
         write: char_write,
         notify: char_notify,
+        broadcast: properties.contains(&CharacteristicProperty::Broadcast),
+        writable_auxiliaries,
         descriptors,
+        control_handle,
         ..Default::default()
-    };
+    }
 }
 
-fn parse_descriptor(descriptor: descriptor::Descriptor) -> Descriptor {
-    // TODO: Add properties
-    return Descriptor {
+fn parse_descriptor(
+    descriptor: descriptor::Descriptor,
+    service_uuid: Uuid,
+    characteristic_uuid: Uuid,
+    context: GattContext,
+) -> Descriptor {
+    let mut desc_read: Option<DescriptorRead> = None;
+    let mut desc_write: Option<DescriptorWrite> = None;
+
+    let permissions = &descriptor.permissions;
+
+    let read_context = context.clone();
+    if descriptor.is_readable() {
+        desc_read = Some(DescriptorRead {
+            read: true,
+            encrypt_read: permissions.contains(&DescriptorPermission::ReadEncryptionRequired),
+            encrypt_authenticated_read: permissions
+                .contains(&DescriptorPermission::ReadAuthenticationRequired),
+            fun: Box::new(move |request: DescriptorReadRequest| {
+                let context_clone = read_context.clone();
+                async move {
+                    on_descriptor_read_request(
+                        context_clone,
+                        request,
+                        service_uuid,
+                        characteristic_uuid,
+                        descriptor.uuid,
+                    )
+                    .await
+                }
+                .boxed()
+            }),
+            ..Default::default()
+        });
+    }
+
+    let write_context = context.clone();
+    if descriptor.is_writeable() {
+        desc_write = Some(DescriptorWrite {
+            write: true,
+            encrypt_write: permissions.contains(&DescriptorPermission::WriteEncryptionRequired),
+            encrypt_authenticated_write: permissions
+                .contains(&DescriptorPermission::WriteAuthenticationRequired),
+            fun: Box::new(move |value: Vec<u8>, request: DescriptorWriteRequest| {
+                let context_clone = write_context.clone();
+                async move {
+                    on_descriptor_write_request(
+                        context_clone,
+                        request,
+                        service_uuid,
+                        characteristic_uuid,
+                        descriptor.uuid,
+                        value,
+                    )
+                    .await
+                }
+                .boxed()
+            }),
+            ..Default::default()
+        });
+    }
+
+    Descriptor {
         uuid: descriptor.uuid,
+        authorize: permissions.contains(&DescriptorPermission::AuthorizationRequired),
+        read: desc_read,
+        write: desc_write,
         ..Default::default()
-    };
+    }
 }
 
 /// Handle Requests
 async fn on_read_request(
-    sender_tx: Sender<PeripheralEvent>,
+    context: GattContext,
     request: CharacteristicReadRequest,
     service_uuid: Uuid,
     characteristic: Uuid,
 ) -> Result<Vec<u8>, ReqError> {
-    if let Err(err) = sender_tx
+    on_activity(&context, request.device_address, Some(request.mtu)).await;
+
+    // A stored value is answered directly, without asking the application
+    if let Some(value) = context.database.get(service_uuid, characteristic) {
+        return slice(&value, request.offset).map_err(ReqError::from);
+    }
+
+    let client = context.clients.client(request.device_address).await;
+    if let Some(response) =
+        context
+            .reads
+            .read(&client, service_uuid, characteristic, request.offset)
+    {
+        return response.map_err(ReqError::from);
+    }
+
+    let (resp_tx, resp_rx) = oneshot::channel::<ReadResponse>();
+    if let Err(err) = context
+        .sender_tx
         .send(PeripheralEvent::DidReceiveReadRequest {
-            client: request.device_address.to_string(),
+            client: client.clone(),
             service: service_uuid,
-            characteristic: characteristic,
+            characteristic,
+            offset: request.offset,
+            responder: resp_tx,
         })
         .await
     {
-        eprintln!("Error sending read request event: {:?}", err);
+        log::error!("Error sending read request event: {:?}", err);
+        return Err(ReqError::Failed);
+    }
+
+    // Wait for the application to respond, a dropped responder fails the request
+    match resp_rx.await {
+        Ok(Ok(value)) => context
+            .reads
+            .store(&client, service_uuid, characteristic, request.offset, value)
+            .map_err(ReqError::from),
+        Ok(Err(error)) => Err(error.into()),
+        Err(err) => {
+            log::error!("Read request was not answered: {:?}", err);
+            Err(ReqError::Failed)
+        }
     }
-    Ok(vec![])
 }
 
 async fn on_write_request(
-    sender_tx: Sender<PeripheralEvent>,
+    context: GattContext,
     request: CharacteristicWriteRequest,
     service_uuid: Uuid,
     characteristic: Uuid,
     value: Vec<u8>,
 ) -> Result<(), ReqError> {
-    if let Err(err) = sender_tx
+    on_activity(&context, request.device_address, Some(request.mtu)).await;
+    let client = context.clients.client(request.device_address).await;
+
+    // Chunks of a long write are collected until the complete value is known
+    let mut value = value;
+    if request.op_type == WriteOp::Reliable || request.offset > 0 {
+        match context.prepared_writes.append(
+            &client,
+            service_uuid,
+            characteristic,
+            request.offset,
+            &value,
+        )? {
+            Chunk::Complete(complete) => value = complete,
+            Chunk::Pending(sequence) => {
+                tokio::spawn(complete_idle_write(
+                    context,
+                    client,
+                    service_uuid,
+                    characteristic,
+                    sequence,
+                ));
+                return Ok(());
+            }
+        }
+    }
+
+    let (resp_tx, resp_rx) = oneshot::channel::<WriteResponse>();
+    if let Err(err) = context
+        .sender_tx
         .send(PeripheralEvent::DidReceiveWriteRequest {
-            client: request.device_address.to_string(),
+            client,
             service: service_uuid,
-            characteristic: characteristic,
+            characteristic,
+            value: value.clone(),
+            responder: resp_tx,
+        })
+        .await
+    {
+        log::error!("Error sending write request event: {:?}", err);
+        return Err(ReqError::Failed);
+    }
+
+    // Write commands are never acknowledged, so the central does not wait for the application
+    if request.op_type == WriteOp::Command {
+        tokio::spawn(async move {
+            if let Ok(Ok(())) = resp_rx.await {
+                context
+                    .database
+                    .store_written(service_uuid, characteristic, value);
+            }
+        });
+        return Ok(());
+    }
+
+    match resp_rx.await {
+        Ok(Ok(())) => {
+            context
+                .database
+                .store_written(service_uuid, characteristic, value);
+            Ok(())
+        }
+        Ok(Err(error)) => Err(error.into()),
+        Err(err) => {
+            log::error!("Write request was not answered: {:?}", err);
+            Err(ReqError::Failed)
+        }
+    }
+}
+
+/// A long write whose length is a multiple of the chunk size has no short last chunk,
+/// it is reported once no further chunk arrived for a while. BlueZ only hands over the
+/// next chunk after the previous one was answered, so the central was answered already
+/// and a rejection by the application cannot reach it
+async fn complete_idle_write(
+    context: GattContext,
+    client: Client,
+    service_uuid: Uuid,
+    characteristic: Uuid,
+    sequence: u64,
+) {
+    tokio::time::sleep(PREPARED_WRITE_IDLE).await;
+    let Some(value) =
+        context
+            .prepared_writes
+            .take_if_idle(&client, service_uuid, characteristic, sequence)
+    else {
+        return;
+    };
+
+    let (resp_tx, resp_rx) = oneshot::channel::<WriteResponse>();
+    if let Err(err) = context
+        .sender_tx
+        .send(PeripheralEvent::DidReceiveWriteRequest {
+            client,
+            service: service_uuid,
+            characteristic,
+            value: value.clone(),
+            responder: resp_tx,
+        })
+        .await
+    {
+        log::error!("Error sending write request event: {:?}", err);
+        return;
+    }
+    match resp_rx.await {
+        Ok(Ok(())) => context
+            .database
+            .store_written(service_uuid, characteristic, value),
+        Ok(Err(error)) => log::error!(
+            "Long write was already acknowledged, the central did not see {}",
+            error
+        ),
+        Err(_) => {}
+    }
+}
+
+async fn on_descriptor_read_request(
+    context: GattContext,
+    request: DescriptorReadRequest,
+    service_uuid: Uuid,
+    characteristic: Uuid,
+    descriptor: Uuid,
+) -> Result<Vec<u8>, ReqError> {
+    on_activity(&context, request.device_address, None).await;
+
+    if let Some(value) = context
+        .database
+        .get_descriptor(service_uuid, characteristic, descriptor)
+    {
+        return slice(&value, request.offset).map_err(ReqError::from);
+    }
+
+    let client = context.clients.client(request.device_address).await;
+    let (resp_tx, resp_rx) = oneshot::channel::<ReadResponse>();
+    if let Err(err) = context
+        .sender_tx
+        .send(PeripheralEvent::DidReceiveDescriptorReadRequest {
+            client,
+            service: service_uuid,
+            characteristic,
+            descriptor,
+            offset: request.offset,
+            responder: resp_tx,
+        })
+        .await
+    {
+        log::error!("Error sending descriptor read request event: {:?}", err);
+        return Err(ReqError::Failed);
+    }
+
+    match resp_rx.await {
+        Ok(Ok(value)) => slice(&value, request.offset).map_err(ReqError::from),
+        Ok(Err(error)) => Err(error.into()),
+        Err(err) => {
+            log::error!("Descriptor read request was not answered: {:?}", err);
+            Err(ReqError::Failed)
+        }
+    }
+}
+
+async fn on_descriptor_write_request(
+    context: GattContext,
+    request: DescriptorWriteRequest,
+    service_uuid: Uuid,
+    characteristic: Uuid,
+    descriptor: Uuid,
+    value: Vec<u8>,
+) -> Result<(), ReqError> {
+    on_activity(&context, request.device_address, None).await;
+    let client = context.clients.client(request.device_address).await;
+    let (resp_tx, resp_rx) = oneshot::channel::<WriteResponse>();
+    if let Err(err) = context
+        .sender_tx
+        .send(PeripheralEvent::DidReceiveDescriptorWriteRequest {
+            client,
+            service: service_uuid,
+            characteristic,
+            descriptor,
+            offset: request.offset,
             value,
+            responder: resp_tx,
         })
         .await
     {
-        eprintln!("Error sending read request event: {:?}", err);
+        log::error!("Error sending descriptor write request event: {:?}", err);
+        return Err(ReqError::Failed);
+    }
+
+    match resp_rx.await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(error)) => Err(error.into()),
+        Err(err) => {
+            log::error!("Descriptor write request was not answered: {:?}", err);
+            Err(ReqError::Failed)
+        }
+    }
+}
+
+/// BlueZ only forwards a fixed set of errors, everything else is reported as Failed
+impl From<AttError> for ReqError {
+    fn from(error: AttError) -> Self {
+        match error {
+            AttError::ReadNotPermitted | AttError::WriteNotPermitted => ReqError::NotPermitted,
+            AttError::RequestNotSupported => ReqError::NotSupported,
+            AttError::InvalidOffset => ReqError::InvalidOffset,
+            AttError::InsufficientAuthentication | AttError::InsufficientAuthorization => {
+                ReqError::NotAuthorized
+            }
+            AttError::InvalidAttributeValueLength => ReqError::InvalidValueLength,
+            AttError::ProcedureAlreadyInProgress => ReqError::InProgress,
+            _ => ReqError::Failed,
+        }
     }
-    Ok(())
 }
 
 async fn on_char_notify(
-    sender_tx: Sender<PeripheralEvent>,
+    context: GattContext,
     notifier: CharacteristicNotifier,
     service_uuid: Uuid,
     characteristic: Uuid,
 ) {
-    if let Err(err) = sender_tx
+    let client = match context.clients.resolve().await {
+        Some(address) => {
+            on_activity(&context, address, None).await;
+            Some(context.clients.client(address).await)
+        }
+        None => None,
+    };
+
+    // Only characteristics which cannot notify use StartNotify,
+    // a central subscribing to them enabled indications
+    let stopped = notifier.stopped();
+    let session =
+        context
+            .notifiers
+            .add_notifier(service_uuid, characteristic, client.clone(), notifier);
+    send_subscribe_event(
+        &context,
+        client,
+        service_uuid,
+        characteristic,
+        ClientConfiguration::INDICATE,
+    )
+    .await;
+
+    // BlueZ waits for this function to return before acknowledging the subscription,
+    // so the end of the session is awaited in the background
+    tokio::spawn(async move {
+        stopped.await;
+        end_session(&context, service_uuid, characteristic, session).await;
+    });
+}
+
+/// Handle the notification sessions acquired for a characteristic,
+/// until the application is unregistered
+async fn on_char_control(
+    context: GattContext,
+    mut control: CharacteristicControl,
+    service_uuid: Uuid,
+    characteristic: Uuid,
+) {
+    while let Some(event) = control.next().await {
+        let CharacteristicControlEvent::Notify(writer) = event else {
+            continue;
+        };
+        let address = writer.device_address();
+        // bluer keeps 5 bytes of the MTU BlueZ reported as a margin
+        let mtu = u16::try_from(writer.mtu() + 5).unwrap_or(u16::MAX);
+        on_activity(&context, address, Some(mtu)).await;
+        let client = context.clients.client(address).await;
+
+        let writer = Arc::new(writer);
+        let session = context.notifiers.add_writer(
+            service_uuid,
+            characteristic,
+            client.clone(),
+            writer.clone(),
+        );
+        // BlueZ hides the CCCD value, notifications are used whenever
+        // the characteristic supports them
+        send_subscribe_event(
+            &context,
+            Some(client),
+            service_uuid,
+            characteristic,
+            ClientConfiguration::NOTIFY,
+        )
+        .await;
+
+        let context = context.clone();
+        tokio::spawn(async move {
+            closed(&writer).await;
+            end_session(&context, service_uuid, characteristic, session).await;
+        });
+    }
+}
+
+async fn closed(writer: &CharacteristicWriter) {
+    if let Err(err) = writer.closed().await {
+        log::warn!(
+            "Error waiting for the notification session to end: {:?}",
+            err
+        );
+    }
+}
+
+async fn send_subscribe_event(
+    context: &GattContext,
+    client: Option<Client>,
+    service_uuid: Uuid,
+    characteristic: Uuid,
+    configuration: ClientConfiguration,
+) {
+    if let Err(err) = context
+        .sender_tx
         .send(PeripheralEvent::DidSubscribeToCharacteristic {
-            client: "".to_string(), // Find ClientAddress
+            client,
             service: service_uuid,
-            characteristic: characteristic,
+            characteristic,
+            configuration,
         })
         .await
     {
-        eprintln!("Error sending read request event: {:?}", err);
+        log::warn!("Error sending subscribe event: {:?}", err);
     }
-    println!("Notify Requested");
-
-    // notifier.notify(vec![1, 2, 4]).await;
+}
 
-    notifier.stopped().await;
-    if let Err(err) = sender_tx
+async fn end_session(
+    context: &GattContext,
+    service_uuid: Uuid,
+    characteristic: Uuid,
+    session: u64,
+) {
+    // Sessions detached on disconnect were already reported
+    let Some(client) = context
+        .notifiers
+        .remove(service_uuid, characteristic, session)
+    else {
+        return;
+    };
+    if let Err(err) = context
+        .sender_tx
         .send(PeripheralEvent::DidUnsubscribeFromCharacteristic {
-            client: "".to_string(), // Find ClientAddress
+            client,
             service: service_uuid,
-            characteristic: characteristic,
+            characteristic,
         })
         .await
     {
-        eprintln!("Error sending read request event: {:?}", err);
+        log::warn!("Error sending unsubscribe event: {:?}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gatt::characteristic::Characteristic as GattCharacteristic;
+
+    fn service_with(properties: Vec<CharacteristicProperty>) -> service::Service {
+        service::Service {
+            uuid: Uuid::from_u128(1),
+            primary: true,
+            characteristics: vec![GattCharacteristic {
+                uuid: Uuid::from_u128(2),
+                properties,
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn encryption_required_subscriptions_are_rejected() {
+        for property in [
+            CharacteristicProperty::NotifyEncryptionRequired,
+            CharacteristicProperty::IndicateEncryptionRequired,
+        ] {
+            let service = service_with(vec![CharacteristicProperty::Read, property]);
+            assert!(check_properties(&service).is_err());
+        }
+    }
+
+    #[test]
+    fn only_indicate_only_characteristics_confirm() {
+        let indicating = |properties| GattCharacteristic {
+            properties,
+            ..Default::default()
+        };
+        assert!(check_confirmable(&indicating(vec![CharacteristicProperty::Indicate])).is_ok());
+        assert!(check_confirmable(&indicating(vec![
+            CharacteristicProperty::Notify,
+            CharacteristicProperty::Indicate,
+        ]))
+        .is_err());
+        assert!(check_confirmable(&indicating(vec![CharacteristicProperty::Notify])).is_err());
+    }
+
+    #[test]
+    fn extended_properties_become_flags() {
+        let mut service = service_with(vec![CharacteristicProperty::Write]);
+        service.characteristics[0].descriptors =
+            vec![descriptor::Descriptor::extended_properties(true, true)];
+        assert!(check_descriptors(&service).is_ok());
+        let descriptor = extended_properties(&service.characteristics[0]).unwrap();
+        assert_eq!(extended_flags(descriptor), (true, true));
+    }
+
+    #[test]
+    fn reliable_write_needs_a_writable_characteristic() {
+        let mut service = service_with(vec![CharacteristicProperty::Read]);
+        service.characteristics[0].descriptors =
+            vec![descriptor::Descriptor::extended_properties(true, false)];
+        assert!(check_descriptors(&service).is_err());
+        service.characteristics[0].descriptors =
+            vec![descriptor::Descriptor::extended_properties(false, true)];
+        assert!(check_descriptors(&service).is_ok());
+    }
+
+    #[test]
+    fn plain_subscriptions_are_accepted() {
+        let service = service_with(vec![
+            CharacteristicProperty::Read,
+            CharacteristicProperty::Notify,
+            CharacteristicProperty::Indicate,
+        ]);
+        assert!(check_properties(&service).is_ok());
     }
-    println!("Notify Stopped");
 }
@@ -0,0 +1,96 @@
+use crate::gatt::client::{AddressType, Client};
+use bluer::{Adapter, Address};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Describes the centrals talking to the GATT server
+#[derive(Debug, Clone)]
+pub struct Clients {
+    adapter: Adapter,
+    // Centrals which used this server since they connected, with the MTU last reported.
+    // BlueZ only reports the MTU along with ATT requests
+    active: Arc<Mutex<HashMap<Address, Option<u16>>>>,
+}
+
+impl Clients {
+    pub fn new(adapter: Adapter) -> Self {
+        Clients {
+            adapter,
+            active: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Describe the central with the given address, fields the adapter
+    /// no longer knows about are left empty
+    pub async fn client(&self, address: Address) -> Client {
+        let address_type = match self.adapter.device(address) {
+            Ok(device) => device.address_type().await.ok(),
+            Err(_) => None,
+        };
+        Client {
+            id: address.to_string(),
+            address: Some(address.to_string()),
+            address_type: address_type.and_then(|address_type| match address_type {
+                bluer::AddressType::LePublic => Some(AddressType::Public),
+                bluer::AddressType::LeRandom => Some(AddressType::Random),
+                bluer::AddressType::BrEdr => None,
+            }),
+            mtu: self.active.lock().unwrap().get(&address).copied().flatten(),
+        }
+    }
+
+    /// Remember that the central used this server, along with the MTU if the request carried one.
+    /// Returns `true` for the first activity since the central connected
+    pub fn record_activity(&self, address: Address, mtu: Option<u16>) -> bool {
+        let mut active = self.active.lock().unwrap();
+        let first = !active.contains_key(&address);
+        let known = active.entry(address).or_default();
+        if mtu.is_some() {
+            *known = mtu;
+        }
+        first
+    }
+
+    /// MTU last reported for the central, `client` is identified by its address
+    pub fn mtu(&self, client: &Client) -> Option<u16> {
+        let address: Address = client.id.parse().ok()?;
+        self.active.lock().unwrap().get(&address).copied().flatten()
+    }
+
+    /// Forget a disconnected central, returns whether it had used this server
+    pub fn forget(&self, address: Address) -> bool {
+        self.active.lock().unwrap().remove(&address).is_some()
+    }
+
+    /// The central behind a StartNotify session, BlueZ does not pass the device along,
+    /// so it is only known while a single central is connected
+    pub async fn resolve(&self) -> Option<Address> {
+        match self.connected_devices().await.as_slice() {
+            [address] => Some(*address),
+            _ => None,
+        }
+    }
+
+    pub async fn connected_devices(&self) -> Vec<Address> {
+        let addresses = match self.adapter.device_addresses().await {
+            Ok(addresses) => addresses,
+            Err(err) => {
+                log::error!("Error listing devices: {:?}", err);
+                return vec![];
+            }
+        };
+
+        let mut connected = vec![];
+        for address in addresses {
+            let Ok(device) = self.adapter.device(address) else {
+                continue;
+            };
+            if device.is_connected().await.unwrap_or(false) {
+                connected.push(address);
+            }
+        }
+        connected
+    }
+}
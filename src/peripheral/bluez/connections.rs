@@ -0,0 +1,129 @@
+use super::characteristic_utils::GattContext;
+use crate::gatt::peripheral_event::PeripheralEvent;
+use bluer::{Adapter, AdapterEvent, Address, DeviceEvent, DeviceProperty};
+use futures::{
+    stream::{BoxStream, SelectAll},
+    StreamExt,
+};
+use std::collections::HashSet;
+
+/// Turn device disconnections into events for the centrals which used this server,
+/// runs until the adapter goes away. Outgoing connections and other profiles, e.g. audio,
+/// share the adapter, so connections are reported with the first request instead
+pub async fn monitor_connections(adapter: Adapter, context: GattContext) {
+    let adapter_events = match adapter.events().await {
+        Ok(events) => events,
+        Err(err) => {
+            log::error!("Error listening to adapter events: {:?}", err);
+            return;
+        }
+    };
+    let mut adapter_events = Box::pin(adapter_events);
+
+    let mut device_events: SelectAll<BoxStream<'static, (Address, DeviceEvent)>> = SelectAll::new();
+    let mut watched: HashSet<Address> = HashSet::new();
+
+    for address in adapter.device_addresses().await.unwrap_or_default() {
+        watch_device(&adapter, address, &mut watched, &mut device_events).await;
+    }
+
+    loop {
+        tokio::select! {
+            Some(event) = adapter_events.next() => match event {
+                AdapterEvent::DeviceAdded(address) => {
+                    watch_device(&adapter, address, &mut watched, &mut device_events).await;
+                }
+                AdapterEvent::DeviceRemoved(address) => {
+                    watched.remove(&address);
+                }
+                AdapterEvent::PropertyChanged(_) => {}
+            },
+            Some((address, event)) = device_events.next() => {
+                if let DeviceEvent::PropertyChanged(DeviceProperty::Connected(false)) = event {
+                    on_disconnected(&context, address).await;
+                }
+            },
+            else => break,
+        }
+    }
+}
+
+async fn watch_device(
+    adapter: &Adapter,
+    address: Address,
+    watched: &mut HashSet<Address>,
+    device_events: &mut SelectAll<BoxStream<'static, (Address, DeviceEvent)>>,
+) {
+    if !watched.insert(address) {
+        return;
+    }
+    let events = match adapter.device(address) {
+        Ok(device) => device.events().await,
+        Err(err) => Err(err),
+    };
+    match events {
+        Ok(events) => device_events.push(events.map(move |event| (address, event)).boxed()),
+        Err(err) => {
+            watched.remove(&address);
+            log::error!("Error listening to device {} events: {:?}", address, err);
+        }
+    }
+}
+
+/// Record a request or subscription of the central, the first one since it connected
+/// is reported as the connection
+pub async fn on_activity(context: &GattContext, address: Address, mtu: Option<u16>) {
+    if context.clients.record_activity(address, mtu) {
+        let client = context.clients.client(address).await;
+        send_event(context, PeripheralEvent::DidConnect { client }).await;
+    }
+}
+
+async fn on_disconnected(context: &GattContext, address: Address) {
+    let client = context.clients.client(address).await;
+    // Nothing was reported for centrals which never used this server
+    if !context.clients.forget(address) {
+        return;
+    }
+
+    // Drop everything attributed to this central before reporting the disconnect
+    context.reads.forget(&client);
+    for (service, characteristic) in context.prepared_writes.forget(&client) {
+        send_event(
+            context,
+            PeripheralEvent::DidCancelPreparedWrite {
+                client: client.clone(),
+                service,
+                characteristic,
+            },
+        )
+        .await;
+    }
+    for (service, characteristic) in context.notifiers.detach_client(&client) {
+        send_event(
+            context,
+            PeripheralEvent::DidUnsubscribeFromCharacteristic {
+                client: Some(client.clone()),
+                service,
+                characteristic,
+            },
+        )
+        .await;
+    }
+
+    // BlueZ does not expose the HCI disconnect reason
+    send_event(
+        context,
+        PeripheralEvent::DidDisconnect {
+            client,
+            reason: None,
+        },
+    )
+    .await;
+}
+
+async fn send_event(context: &GattContext, event: PeripheralEvent) {
+    if let Err(err) = context.sender_tx.send(event).await {
+        log::error!("Error sending connection event: {:?}", err);
+    }
+}
@@ -1,14 +1,46 @@
+mod advertisement_utils;
 mod characteristic_utils;
+mod clients;
+mod connections;
+mod notifiers;
+mod prepared_writes;
 
-use crate::gatt::{peripheral_event::PeripheralEvent, service};
+use super::PeripheralBackend;
+use crate::{
+    adv::{
+        advertisement_data::AdvertisementData,
+        payload::{self, Layout},
+    },
+    gatt::{
+        characteristic::Characteristic,
+        database::{GattDatabase, ValueUpdate},
+        notification::OversizePolicy,
+        peripheral_event::PeripheralEvent,
+        read_cache::ReadCache,
+        service,
+    },
+    Error, ErrorType,
+};
+use advertisement_utils::parse_advertisement;
 use bluer::{
-    adv::{Advertisement, AdvertisementHandle},
+    adv::AdvertisementHandle,
     gatt::local::{Application, ApplicationHandle},
-    Adapter, Error,
+    Adapter,
+};
+use characteristic_utils::{
+    check_confirmable, check_descriptors, check_properties, parse_services, GattContext,
+};
+use clients::Clients;
+use notifiers::Notifiers;
+use prepared_writes::PreparedWrites;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc::{Sender, UnboundedReceiver},
+    task::JoinHandle,
 };
-use characteristic_utils::parse_services;
-use std::collections::{BTreeMap, BTreeSet};
-use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -18,10 +50,18 @@ pub struct Peripheral {
     adv_handle: Option<AdvertisementHandle>,
     app_handle: Option<ApplicationHandle>,
     sender_tx: Sender<PeripheralEvent>,
+    database: GattDatabase,
+    notifiers: Notifiers,
+    clients: Clients,
+    reads: ReadCache,
+    prepared_writes: PreparedWrites,
+    connection_monitor: JoinHandle<()>,
+    value_updates: JoinHandle<()>,
+    oversize_policy: Arc<Mutex<OversizePolicy>>,
 }
 
-impl Peripheral {
-    pub async fn new(sender_tx: Sender<PeripheralEvent>) -> Result<Self, Error> {
+impl PeripheralBackend for Peripheral {
+    async fn new(sender_tx: Sender<PeripheralEvent>) -> Result<Self, Error> {
         let session = bluer::Session::new().await?;
         let adapter = session.default_adapter().await?;
         adapter.set_powered(true).await?;
@@ -31,45 +71,75 @@ impl Peripheral {
             adapter.address().await?
         );
 
+        let (database, updates_rx) = GattDatabase::new();
+        let notifiers = Notifiers::default();
+        let oversize_policy = Arc::new(Mutex::new(OversizePolicy::default()));
+        let clients = Clients::new(adapter.clone());
+        let reads = ReadCache::default();
+        let prepared_writes = PreparedWrites::default();
+        let connection_monitor = tokio::spawn(connections::monitor_connections(
+            adapter.clone(),
+            GattContext {
+                sender_tx: sender_tx.clone(),
+                database: database.clone(),
+                notifiers: notifiers.clone(),
+                clients: clients.clone(),
+                reads: reads.clone(),
+                prepared_writes: prepared_writes.clone(),
+            },
+        ));
+        let value_updates = tokio::spawn(push_value_updates(
+            updates_rx,
+            notifiers.clone(),
+            clients.clone(),
+            oversize_policy.clone(),
+        ));
+
         Ok(Peripheral {
             adapter,
             services: Vec::new(),
             adv_handle: None,
             app_handle: None,
             sender_tx,
+            database,
+            notifiers,
+            clients,
+            reads,
+            prepared_writes,
+            connection_monitor,
+            value_updates,
+            oversize_policy,
         })
     }
 
-    pub async fn is_powered(&self) -> Result<bool, Error> {
+    async fn is_powered(&mut self) -> Result<bool, Error> {
         let result = self.adapter.is_powered().await?;
-        return Ok(result);
+        Ok(result)
     }
 
-    pub async fn is_advertising(&self) -> Result<bool, Error> {
+    async fn is_advertising(&mut self) -> Result<bool, Error> {
         let result = self.adapter.active_advertising_instances().await?;
-        return Ok(result > 0);
+        Ok(result > 0)
     }
 
-    pub async fn start_advertising(&mut self, name: &str, uuids: &[Uuid]) -> Result<(), Error> {
-        let manufacturer_data = BTreeMap::new();
-        // manufacturer_data.insert(MANUFACTURER_ID, vec![0x21, 0x22, 0x23, 0x24]);
-
-        let mut services: BTreeSet<Uuid> = BTreeSet::new();
-        for uuid in uuids {
-            services.insert(*uuid);
-        }
-
-        let le_advertisement = Advertisement {
-            service_uuids: services,
-            manufacturer_data,
-            discoverable: Some(true),
-            local_name: Some(name.to_string()),
-            ..Default::default()
-        };
+    async fn start_advertising(&mut self, data: &AdvertisementData) -> Result<(), Error> {
+        data.check_parameters()?;
+        payload::check(data, Layout::NameInScanResponse)?;
+        let le_advertisement = parse_advertisement(data)?;
         let adv_handle: AdvertisementHandle = self.adapter.advertise(le_advertisement).await?;
 
         let application = Application {
-            services: parse_services(self.services.clone(), self.sender_tx.clone()),
+            services: parse_services(
+                self.services.clone(),
+                GattContext {
+                    sender_tx: self.sender_tx.clone(),
+                    database: self.database.clone(),
+                    notifiers: self.notifiers.clone(),
+                    clients: self.clients.clone(),
+                    reads: self.reads.clone(),
+                    prepared_writes: self.prepared_writes.clone(),
+                },
+            ),
             ..Default::default()
         };
         let app_handle = self.adapter.serve_gatt_application(application).await?;
@@ -78,14 +148,174 @@ impl Peripheral {
         Ok(())
     }
 
-    pub async fn stop_advertising(&mut self) -> Result<(), Error> {
+    async fn stop_advertising(&mut self) -> Result<(), Error> {
         self.adv_handle = None;
         self.app_handle = None;
+        // Unregistering the application ends every notification session
+        for (client, service, characteristic) in self.notifiers.clear() {
+            let event = PeripheralEvent::DidUnsubscribeFromCharacteristic {
+                client,
+                service,
+                characteristic,
+            };
+            if let Err(err) = self.sender_tx.send(event).await {
+                log::warn!("Error sending unsubscribe event: {:?}", err);
+            }
+        }
         Ok(())
     }
 
-    pub async fn add_service(&mut self, service: &service::Service) -> Result<(), Error> {
+    async fn add_service(&mut self, service: &service::Service) -> Result<(), Error> {
+        service.check_descriptors()?;
+        check_descriptors(service)?;
+        check_properties(service)?;
         self.services.push(service.clone());
+        self.database.add_service(service);
         Ok(())
     }
+
+    fn database(&self) -> GattDatabase {
+        self.database.clone()
+    }
+
+    fn set_oversize_policy(&mut self, policy: OversizePolicy) {
+        *self.oversize_policy.lock().unwrap() = policy;
+    }
+
+    async fn update_characteristic(
+        &mut self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    ) -> Result<usize, Error> {
+        self.ensure_characteristic(service, characteristic)?;
+        let policy = *self.oversize_policy.lock().unwrap();
+        notify(
+            &self.notifiers,
+            &self.clients,
+            policy,
+            service,
+            characteristic,
+            value,
+        )
+        .await
+    }
+
+    async fn indicate(
+        &mut self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        check_confirmable(self.ensure_characteristic(service, characteristic)?)?;
+        let policy = *self.oversize_policy.lock().unwrap();
+        let payloads = payloads(
+            &self.notifiers,
+            &self.clients,
+            policy,
+            service,
+            characteristic,
+            value,
+        )
+        .await?;
+        for payload in payloads {
+            self.notifiers
+                .indicate(service, characteristic, payload, timeout)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl Peripheral {
+    fn ensure_characteristic(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+    ) -> Result<&Characteristic, Error> {
+        self.services
+            .iter()
+            .filter(|s| s.uuid == service)
+            .flat_map(|s| s.characteristics.iter())
+            .find(|c| c.uuid == characteristic)
+            .ok_or_else(|| {
+                Error::new(
+                    "CharacteristicNotFound",
+                    "Characteristic is not part of any added service",
+                    ErrorType::Bluez,
+                )
+            })
+    }
+}
+
+impl Drop for Peripheral {
+    fn drop(&mut self) {
+        self.connection_monitor.abort();
+        self.database.close();
+        self.value_updates.abort();
+    }
+}
+
+/// Send values stored in the database to the subscribers of their characteristic
+async fn push_value_updates(
+    mut updates_rx: UnboundedReceiver<ValueUpdate>,
+    notifiers: Notifiers,
+    clients: Clients,
+    oversize_policy: Arc<Mutex<OversizePolicy>>,
+) {
+    while let Some(update) = updates_rx.recv().await {
+        let policy = *oversize_policy.lock().unwrap();
+        if let Err(err) = notify(
+            &notifiers,
+            &clients,
+            policy,
+            update.service,
+            update.characteristic,
+            update.value,
+        )
+        .await
+        {
+            log::warn!("Error sending stored value: {}", err);
+        }
+    }
+}
+
+async fn notify(
+    notifiers: &Notifiers,
+    clients: &Clients,
+    policy: OversizePolicy,
+    service: Uuid,
+    characteristic: Uuid,
+    value: Vec<u8>,
+) -> Result<usize, Error> {
+    let payloads = payloads(notifiers, clients, policy, service, characteristic, value).await?;
+    // BlueZ fans a value out to all subscribed centrals of a session,
+    // so the count is per notification session
+    let mut delivered = usize::MAX;
+    for payload in payloads {
+        let count = notifiers.notify(service, characteristic, payload).await;
+        delivered = delivered.min(count);
+    }
+    Ok(delivered)
+}
+
+/// Apply the oversize policy for the smallest MTU among the subscribers, BlueZ would
+/// otherwise truncate the value. Sessions acquired with AcquireNotify carry the MTU of
+/// their central, the MTU of other centrals is only known once they sent a request
+async fn payloads(
+    notifiers: &Notifiers,
+    clients: &Clients,
+    policy: OversizePolicy,
+    service: Uuid,
+    characteristic: Uuid,
+    value: Vec<u8>,
+) -> Result<Vec<Vec<u8>>, Error> {
+    policy.payloads_for_known(value, notifiers.mtus(service, characteristic, clients))
+}
+
+impl From<bluer::Error> for Error {
+    fn from(err: bluer::Error) -> Self {
+        Error::new(err.kind.to_string(), err.message, ErrorType::Bluez)
+    }
 }
@@ -0,0 +1,298 @@
+use super::clients::Clients;
+use crate::{gatt::client::Client, Error, ErrorType};
+use bluer::gatt::{local::CharacteristicNotifier, CharacteristicWriter};
+use futures::future::join_all;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::Mutex as SessionLock, time::timeout};
+use uuid::Uuid;
+
+/// Longest wait for a Handle Value Confirmation when pushing a value,
+/// the time after which an ATT transaction fails
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How values reach the centrals of a session
+#[derive(Clone)]
+enum Sink {
+    /// Session started with StartNotify, used for characteristics which only indicate.
+    /// Each notifier has a lock of its own, so a central which does not confirm
+    /// an indication only holds up its own session
+    Notifier(Arc<SessionLock<CharacteristicNotifier>>),
+    /// Session acquired with AcquireNotify, which names the central and its MTU
+    Writer(Arc<CharacteristicWriter>),
+}
+
+impl Sink {
+    async fn send(&self, value: Vec<u8>) -> Result<(), String> {
+        match self {
+            Sink::Notifier(notifier) => {
+                let mut notifier = notifier.lock().await;
+                notifier.notify(value).await.map_err(|err| err.to_string())
+            }
+            Sink::Writer(writer) => writer.send(&value).await.map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// Notification session together with the central it was attributed to
+struct Session {
+    id: u64,
+    client: Option<Client>,
+    /// Set once the end of the session was reported on disconnect
+    detached: bool,
+    confirming: bool,
+    /// Largest MTU the sink sends values for, if it is limited by the session
+    mtu: Option<u16>,
+    sink: Sink,
+}
+
+#[derive(Default)]
+struct Sessions {
+    next_id: u64,
+    by_characteristic: HashMap<(Uuid, Uuid), Vec<Session>>,
+}
+
+/// Active notification sessions, keyed by service and characteristic uuid.
+/// The map is only locked to look sessions up, never while sending on them
+#[derive(Clone, Default)]
+pub struct Notifiers {
+    sessions: Arc<Mutex<Sessions>>,
+}
+
+impl fmt::Debug for Notifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Notifiers")
+    }
+}
+
+impl Notifiers {
+    /// Register a session opened with StartNotify, returns the id to `remove` it with
+    pub fn add_notifier(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        client: Option<Client>,
+        notifier: CharacteristicNotifier,
+    ) -> u64 {
+        let confirming = notifier.confirming();
+        let sink = Sink::Notifier(Arc::new(SessionLock::new(notifier)));
+        self.add(service, characteristic, client, confirming, None, sink)
+    }
+
+    /// Register a session acquired with AcquireNotify, returns the id to `remove` it with
+    pub fn add_writer(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        client: Client,
+        writer: Arc<CharacteristicWriter>,
+    ) -> u64 {
+        // The writer takes values of up to `mtu()` bytes, bluer keeps a margin below
+        // the ATT MTU, so this is the MTU for which notifications carry that much
+        let mtu = u16::try_from(writer.mtu() + 3).unwrap_or(u16::MAX);
+        let sink = Sink::Writer(writer);
+        self.add(
+            service,
+            characteristic,
+            Some(client),
+            false,
+            Some(mtu),
+            sink,
+        )
+    }
+
+    fn add(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        client: Option<Client>,
+        confirming: bool,
+        mtu: Option<u16>,
+        sink: Sink,
+    ) -> u64 {
+        let mut sessions = self.sessions.lock().unwrap();
+        let id = sessions.next_id;
+        sessions.next_id += 1;
+        sessions
+            .by_characteristic
+            .entry((service, characteristic))
+            .or_default()
+            .push(Session {
+                id,
+                client,
+                detached: false,
+                confirming,
+                mtu,
+                sink,
+            });
+        id
+    }
+
+    /// MTU for each session of the characteristic, the one the session is limited to or
+    /// the one last reported by the attributed client, `None` if neither is known
+    pub fn mtus(&self, service: Uuid, characteristic: Uuid, clients: &Clients) -> Vec<Option<u16>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .by_characteristic
+            .get(&(service, characteristic))
+            .map(|sessions| {
+                sessions
+                    .iter()
+                    .map(|session| {
+                        session.mtu.or_else(|| {
+                            session
+                                .client
+                                .as_ref()
+                                .and_then(|client| clients.mtu(client))
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Drop a session which was stopped by the central, returns the client it was attributed to,
+    /// `None` if the end of the session was already reported on disconnect
+    pub fn remove(&self, service: Uuid, characteristic: Uuid, id: u64) -> Option<Option<Client>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let key = (service, characteristic);
+        let list = sessions.by_characteristic.get_mut(&key)?;
+        let index = list.iter().position(|session| session.id == id)?;
+        let session = list.remove(index);
+        if list.is_empty() {
+            sessions.by_characteristic.remove(&key);
+        }
+        (!session.detached).then_some(session.client)
+    }
+
+    /// Remove the attribution of running sessions to a disconnected client,
+    /// returns the service and characteristic uuids it was subscribed to
+    pub fn detach_client(&self, client: &Client) -> Vec<(Uuid, Uuid)> {
+        let mut detached = vec![];
+        for (key, sessions) in self.sessions.lock().unwrap().by_characteristic.iter_mut() {
+            for session in sessions.iter_mut() {
+                if session.client.as_ref() == Some(client) {
+                    session.client = None;
+                    session.detached = true;
+                    detached.push(*key);
+                }
+            }
+        }
+        detached
+    }
+
+    /// Send the value on every session of the characteristic, returns how many sessions accepted it.
+    /// Indications which are not confirmed within the ATT timeout count as not delivered
+    pub async fn notify(&self, service: Uuid, characteristic: Uuid, value: Vec<u8>) -> usize {
+        let sessions = self.sessions_of(service, characteristic, false);
+        let results = join_all(sessions.iter().map(|(client, sink)| {
+            let value = value.clone();
+            async move {
+                let result = timeout(CONFIRM_TIMEOUT, sink.send(value)).await;
+                match result {
+                    Ok(Ok(())) => true,
+                    Ok(Err(err)) => {
+                        log::warn!("Error sending notification to {:?}: {}", client, err);
+                        false
+                    }
+                    Err(_) => {
+                        log::warn!("Indication to {:?} was not confirmed in time", client);
+                        false
+                    }
+                }
+            }
+        }))
+        .await;
+        results.into_iter().filter(|delivered| *delivered).count()
+    }
+
+    /// Send the value on every indication session of the characteristic,
+    /// resolves once each central confirmed it
+    pub async fn indicate(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+        confirm_timeout: Duration,
+    ) -> Result<(), Error> {
+        let sessions = self.sessions_of(service, characteristic, true);
+        if sessions.is_empty() {
+            return Err(Error::new(
+                "NotSubscribed",
+                "No confirmed indication session is active for this characteristic",
+                ErrorType::Bluez,
+            ));
+        }
+
+        let results = join_all(
+            sessions
+                .iter()
+                .map(|(_, sink)| timeout(confirm_timeout, sink.send(value.clone()))),
+        )
+        .await;
+
+        for result in results {
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => {
+                    return Err(Error::new(
+                        "IndicationUnconfirmed",
+                        &format!("Indication was not confirmed: {}", err),
+                        ErrorType::Bluez,
+                    ))
+                }
+                Err(_) => {
+                    return Err(Error::new(
+                        "IndicationTimeout",
+                        "Central did not confirm the indication in time",
+                        ErrorType::Bluez,
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop every session, returns the client, service and characteristic of each one
+    /// whose end was not already reported on disconnect
+    pub fn clear(&self) -> Vec<(Option<Client>, Uuid, Uuid)> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions
+            .by_characteristic
+            .drain()
+            .flat_map(|((service, characteristic), sessions)| {
+                sessions
+                    .into_iter()
+                    .filter(|session| !session.detached)
+                    .map(move |session| (session.client, service, characteristic))
+            })
+            .collect()
+    }
+
+    /// Sessions of the characteristic, only the confirming ones if `confirming_only` is set
+    fn sessions_of(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        confirming_only: bool,
+    ) -> Vec<(Option<Client>, Sink)> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .by_characteristic
+            .get(&(service, characteristic))
+            .map(|sessions| {
+                sessions
+                    .iter()
+                    .filter(|session| session.confirming || !confirming_only)
+                    .map(|session| (session.client.clone(), session.sink.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    gatt::{client::Client, prepared_write::write_at},
+    AttError,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+type Key = (String, Uuid, Uuid);
+
+#[derive(Debug)]
+struct Pending {
+    value: Vec<u8>,
+    sequence: u64,
+}
+
+/// Outcome of adding a chunk to a long write
+#[derive(Debug)]
+pub enum Chunk {
+    Complete(Vec<u8>),
+    /// More chunks are expected, identifies the latest chunk of the write
+    Pending(u64),
+}
+
+/// Long writes in reassembly. BlueZ queues Prepare Writes itself and only hands them
+/// over one by one on Execute Write, without marking the last one
+#[derive(Debug, Clone, Default)]
+pub struct PreparedWrites {
+    pending: Arc<Mutex<HashMap<Key, Pending>>>,
+}
+
+impl PreparedWrites {
+    /// Add a chunk, the write is complete once a chunk does not fill a whole
+    /// Prepare Write request, which carries at most MTU - 5 bytes
+    pub fn append(
+        &self,
+        client: &Client,
+        service: Uuid,
+        characteristic: Uuid,
+        offset: u16,
+        chunk: &[u8],
+    ) -> Result<Chunk, AttError> {
+        let key = (client.id.clone(), service, characteristic);
+        let mut pending = self.pending.lock().unwrap();
+        let mut write = match pending.remove(&key) {
+            Some(write) if offset > 0 => write,
+            _ => Pending {
+                value: vec![],
+                sequence: 0,
+            },
+        };
+        write_at(&mut write.value, offset, chunk)?;
+
+        let full_chunk = client
+            .mtu
+            .is_some_and(|mtu| chunk.len() >= usize::from(mtu.saturating_sub(5)));
+        if !full_chunk {
+            return Ok(Chunk::Complete(write.value));
+        }
+        write.sequence += 1;
+        let sequence = write.sequence;
+        pending.insert(key, write);
+        Ok(Chunk::Pending(sequence))
+    }
+
+    /// Take the value if no chunk was added since `sequence`,
+    /// for writes whose length is a multiple of the chunk size
+    pub fn take_if_idle(
+        &self,
+        client: &Client,
+        service: Uuid,
+        characteristic: Uuid,
+        sequence: u64,
+    ) -> Option<Vec<u8>> {
+        let key = (client.id.clone(), service, characteristic);
+        let mut pending = self.pending.lock().unwrap();
+        if pending.get(&key)?.sequence != sequence {
+            return None;
+        }
+        pending.remove(&key).map(|write| write.value)
+    }
+
+    /// Drop the unfinished writes of a client, returns the service and characteristic uuids
+    pub fn forget(&self, client: &Client) -> Vec<(Uuid, Uuid)> {
+        let mut forgotten = vec![];
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|(id, service, characteristic), _| {
+                if *id != client.id {
+                    return true;
+                }
+                forgotten.push((*service, *characteristic));
+                false
+            });
+        forgotten
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE: Uuid = Uuid::from_u128(1);
+    const CHARACTERISTIC: Uuid = Uuid::from_u128(2);
+
+    fn client() -> Client {
+        let mut client = Client::new("central");
+        // Prepare Write requests carry 18 bytes at this MTU
+        client.mtu = Some(23);
+        client
+    }
+
+    fn append(writes: &PreparedWrites, offset: u16, chunk: &[u8]) -> Result<Chunk, AttError> {
+        writes.append(&client(), SERVICE, CHARACTERISTIC, offset, chunk)
+    }
+
+    #[test]
+    fn short_chunk_completes_the_write() {
+        let writes = PreparedWrites::default();
+        assert!(matches!(
+            append(&writes, 0, &[1; 18]),
+            Ok(Chunk::Pending(1))
+        ));
+        assert!(matches!(
+            append(&writes, 18, &[2; 18]),
+            Ok(Chunk::Pending(2))
+        ));
+        let Ok(Chunk::Complete(value)) = append(&writes, 36, &[3; 4]) else {
+            panic!("write did not complete");
+        };
+        assert_eq!(value.len(), 40);
+        assert_eq!(&value[34..38], &[2, 2, 3, 3]);
+    }
+
+    #[test]
+    fn overlapping_chunk_overwrites() {
+        let writes = PreparedWrites::default();
+        assert!(matches!(
+            append(&writes, 0, &[1; 18]),
+            Ok(Chunk::Pending(_))
+        ));
+        let Ok(Chunk::Complete(value)) = append(&writes, 10, &[2; 10]) else {
+            panic!("write did not complete");
+        };
+        assert_eq!(value.len(), 20);
+        assert_eq!(&value[8..12], &[1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn gap_fails_the_write() {
+        let writes = PreparedWrites::default();
+        assert!(matches!(
+            append(&writes, 0, &[1; 18]),
+            Ok(Chunk::Pending(_))
+        ));
+        assert!(matches!(
+            append(&writes, 19, &[2; 18]),
+            Err(AttError::InvalidOffset)
+        ));
+        // The failed write was dropped, a later chunk cannot continue it
+        assert!(matches!(
+            append(&writes, 18, &[2; 4]),
+            Err(AttError::InvalidOffset)
+        ));
+    }
+
+    #[test]
+    fn offset_zero_starts_a_new_write() {
+        let writes = PreparedWrites::default();
+        assert!(matches!(
+            append(&writes, 0, &[1; 18]),
+            Ok(Chunk::Pending(_))
+        ));
+        let Ok(Chunk::Complete(value)) = append(&writes, 0, &[2; 2]) else {
+            panic!("write did not complete");
+        };
+        assert_eq!(value, vec![2; 2]);
+    }
+
+    #[test]
+    fn idle_write_is_taken_once() {
+        let writes = PreparedWrites::default();
+        assert!(matches!(
+            append(&writes, 0, &[1; 18]),
+            Ok(Chunk::Pending(1))
+        ));
+        assert!(matches!(
+            append(&writes, 18, &[1; 18]),
+            Ok(Chunk::Pending(2))
+        ));
+        let client = client();
+        // A chunk arrived after the first one, the write is not idle since then
+        assert_eq!(
+            writes.take_if_idle(&client, SERVICE, CHARACTERISTIC, 1),
+            None
+        );
+        assert_eq!(
+            writes.take_if_idle(&client, SERVICE, CHARACTERISTIC, 2),
+            Some(vec![1; 36])
+        );
+        assert_eq!(
+            writes.take_if_idle(&client, SERVICE, CHARACTERISTIC, 2),
+            None
+        );
+    }
+
+    #[test]
+    fn forget_cancels_the_writes_of_a_client() {
+        let writes = PreparedWrites::default();
+        assert!(matches!(
+            append(&writes, 0, &[1; 18]),
+            Ok(Chunk::Pending(_))
+        ));
+        assert_eq!(writes.forget(&client()), vec![(SERVICE, CHARACTERISTIC)]);
+        assert!(writes.forget(&client()).is_empty());
+        assert!(matches!(
+            append(&writes, 18, &[1; 4]),
+            Err(AttError::InvalidOffset)
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+use super::mac_extensions::UuidExtension as _;
+use crate::{
+    adv::advertisement_data::{AdvertisementData, LocalName},
+    Error, ErrorType,
+};
+use objc2::{rc::Retained, runtime::AnyObject};
+use objc2_core_bluetooth::{CBAdvertisementDataLocalNameKey, CBAdvertisementDataServiceUUIDsKey};
+use objc2_foundation::{NSArray, NSDictionary, NSString};
+
+/// Peripherals may only advertise a local name and service uuids on CoreBluetooth,
+/// which also decides itself how they are placed into the packets
+pub fn parse_advertisement(
+    data: &AdvertisementData,
+) -> Result<Retained<NSDictionary<NSString, AnyObject>>, Error> {
+    let unsupported = [
+        (
+            matches!(data.local_name, Some(LocalName::Shortened(_))),
+            "shortened local name",
+        ),
+        (!data.solicited_uuids.is_empty(), "solicited uuids"),
+        (!data.service_data.is_empty(), "service data"),
+        (!data.manufacturer_data.is_empty(), "manufacturer data"),
+        (data.appearance.is_some(), "appearance"),
+        (data.include_tx_power, "TX power level"),
+        (!data.discoverable, "non discoverable advertisement"),
+        (
+            data.min_interval.is_some() || data.max_interval.is_some(),
+            "custom advertising interval",
+        ),
+        (data.tx_power.is_some(), "custom TX power"),
+        (data.duration.is_some(), "custom advertising duration"),
+        (data.timeout.is_some(), "timed out advertisement"),
+    ];
+    if let Some((_, field)) = unsupported.iter().find(|(present, _)| *present) {
+        return Err(Error::new(
+            "NotSupported".to_string(),
+            format!("CoreBluetooth cannot advertise a {}", field),
+            ErrorType::CoreBluetooth,
+        ));
+    }
+
+    let mut keys: Vec<&NSString> = vec![];
+    let mut objects: Vec<Retained<AnyObject>> = vec![];
+
+    unsafe {
+        if let Some(local_name) = &data.local_name {
+            keys.push(CBAdvertisementDataLocalNameKey);
+            objects.push(Retained::cast(NSString::from_str(local_name.name())));
+        }
+
+        if !data.service_uuids.is_empty() {
+            keys.push(CBAdvertisementDataServiceUUIDsKey);
+            objects.push(Retained::cast(NSArray::from_vec(
+                data.service_uuids.iter().map(|u| u.to_cbuuid()).collect(),
+            )));
+        }
+    }
+
+    return Ok(NSDictionary::from_vec(&keys, objects));
+}
@@ -1,16 +1,23 @@
-use crate::gatt::{
-    characteristic::Characteristic,
-    properties::{AttributePermission, CharacteristicProperty},
+use crate::{
+    gatt::{
+        characteristic::Characteristic,
+        descriptor::Descriptor,
+        properties::{AttributePermission, CharacteristicProperty, DescriptorPermission},
+        service::Service,
+    },
+    Error, ErrorType, SdpShortUuid,
 };
-use objc2::{rc::Retained, ClassType};
+use objc2::{rc::Retained, runtime::AnyObject, ClassType};
 use objc2_core_bluetooth::{
-    CBAttributePermissions, CBCharacteristic, CBCharacteristicProperties, CBMutableCharacteristic,
+    CBAttributePermissions, CBCharacteristicProperties, CBDescriptor, CBMutableCharacteristic,
+    CBMutableDescriptor,
 };
-use objc2_foundation::NSData;
+use objc2_foundation::{NSArray, NSData, NSString};
+use uuid::Uuid;
 
 use super::mac_extensions::UuidExtension;
 
-pub fn parse_characteristic(characteristic: &Characteristic) -> Retained<CBCharacteristic> {
+pub fn parse_characteristic(characteristic: &Characteristic) -> Retained<CBMutableCharacteristic> {
     unsafe {
         let properties = characteristic
             .properties
@@ -26,47 +33,106 @@ pub fn parse_characteristic(characteristic: &Characteristic) -> Retained<CBChara
                 acc | permission.clone().to_attribute_permission()
             });
 
-        let value_data = characteristic
-            .value
-            .as_ref()
-            .map(|value| NSData::from_vec(value.clone()));
-
+        // Values are kept in the GattDatabase and every read is answered by the delegate,
+        // a value cached by CoreBluetooth could not be changed later
         let mutable_char = CBMutableCharacteristic::initWithType_properties_value_permissions(
             CBMutableCharacteristic::alloc(),
             &characteristic.uuid.to_cbuuid(),
             properties,
-            value_data.as_ref().map(|data| data as &NSData),
+            None,
             permissions,
         );
 
-        // let descriptors: Retained<NSArray<CBDescriptor>> = NSArray::from_vec(
-        //     characteristic
-        //         .descriptors
-        //         .iter()
-        //         .map(|desc| parse_descriptor(desc))
-        //         .collect(),
-        // );
+        let descriptors: Vec<Retained<CBDescriptor>> = characteristic
+            .descriptors
+            .iter()
+            .filter_map(|desc| parse_descriptor(desc))
+            .collect();
+        if !descriptors.is_empty() {
+            mutable_char.setDescriptors(Some(&NSArray::from_vec(descriptors)));
+        }
 
-        // mutable_char.setDescriptors(Some(&descriptors));
+        return mutable_char;
+    }
+}
 
-        return Retained::into_super(mutable_char);
+// CoreBluetooth only publishes read-only descriptors with a fixed value, either a
+// user description as NSString or a presentation format as NSData, and raises an
+// exception for anything else, so other descriptors are rejected when adding the service
+pub fn check_descriptors(service: &Service) -> Result<(), Error> {
+    for characteristic in &service.characteristics {
+        for descriptor in &characteristic.descriptors {
+            let supported = [
+                Uuid::from_sdp_short_uuid(0x2901_u16),
+                Uuid::from_sdp_short_uuid(0x2904_u16),
+            ];
+            if !supported.contains(&descriptor.uuid) {
+                return Err(unsupported_descriptor(
+                    descriptor,
+                    "CoreBluetooth only publishes 0x2901 and 0x2904",
+                ));
+            }
+            if descriptor.value.is_none() || !descriptor.is_readable() {
+                return Err(unsupported_descriptor(
+                    descriptor,
+                    "CoreBluetooth needs a readable descriptor with a value",
+                ));
+            }
+        }
     }
+    return Ok(());
 }
 
-// pub fn parse_descriptor(descriptor: &Descriptor) -> Retained<CBDescriptor> {
-//     unsafe {
-//         let value_data = descriptor
-//             .value
-//             .as_ref()
-//             .map(|value| NSData::from_vec(value.clone()));
+fn unsupported_descriptor(descriptor: &Descriptor, reason: &str) -> Error {
+    return Error::new(
+        "UnsupportedDescriptor".to_string(),
+        format!("Descriptor {} cannot be added, {}", descriptor.uuid, reason),
+        ErrorType::CoreBluetooth,
+    );
+}
 
-//         return Retained::into_super(CBMutableDescriptor::initWithType_value(
-//             CBMutableDescriptor::alloc(),
-//             &descriptor.uuid.to_cbuuid(),
-//             value_data.as_ref().map(|data| data as &AnyObject),
-//         ));
-//     }
-// }
+pub fn parse_descriptor(descriptor: &Descriptor) -> Option<Retained<CBDescriptor>> {
+    let unsupported = descriptor.permissions.iter().find(|permission| {
+        !matches!(
+            permission,
+            DescriptorPermission::Readable | DescriptorPermission::Writeable
+        )
+    });
+    if let Some(permission) = unsupported {
+        log::warn!(
+            "Descriptor {} requires {:?}, which CoreBluetooth does not support",
+            descriptor.uuid,
+            permission
+        );
+    }
+    if descriptor.is_writeable() {
+        log::warn!(
+            "Descriptor {} is published read-only, CoreBluetooth does not forward descriptor writes",
+            descriptor.uuid
+        );
+    }
+
+    // Descriptors without a value were rejected by check_descriptors
+    let value = descriptor.value.as_ref()?;
+
+    let value: Retained<AnyObject> = if descriptor.uuid == Uuid::from_sdp_short_uuid(0x2901_u16) {
+        unsafe { Retained::cast(NSString::from_str(&String::from_utf8_lossy(value))) }
+    } else if descriptor.uuid == Uuid::from_sdp_short_uuid(0x2904_u16) {
+        unsafe { Retained::cast(NSData::from_vec(value.clone())) }
+    } else {
+        return None;
+    };
+
+    unsafe {
+        return Some(Retained::into_super(
+            CBMutableDescriptor::initWithType_value(
+                CBMutableDescriptor::alloc(),
+                &descriptor.uuid.to_cbuuid(),
+                Some(&value),
+            ),
+        ));
+    }
+}
 
 impl CharacteristicProperty {
     fn to_cb_property(self) -> CBCharacteristicProperties {
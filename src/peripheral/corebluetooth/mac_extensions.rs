@@ -1,5 +1,6 @@
+use crate::gatt::client::Client;
 use objc2::rc::Retained;
-use objc2_core_bluetooth::{CBCharacteristic, CBService, CBUUID};
+use objc2_core_bluetooth::{CBCentral, CBCharacteristic, CBService, CBUUID};
 use objc2_foundation::NSString;
 use uuid::Uuid;
 
@@ -54,3 +55,21 @@ impl CbUuidExtension for &CBUUID {
         uuid_string.parse().unwrap()
     }
 }
+
+pub trait ClientExtension {
+    fn to_client(self) -> Client;
+}
+
+impl ClientExtension for &CBCentral {
+    // CoreBluetooth hides the address of centrals, only the identifier is known.
+    // The MTU is derived from the largest notification payload, which is MTU - 3
+    fn to_client(self) -> Client {
+        unsafe {
+            let mtu = u16::try_from(self.maximumUpdateValueLength() + 3).unwrap_or(u16::MAX);
+            return Client {
+                mtu: Some(mtu),
+                ..Client::new(self.identifier().to_string())
+            };
+        }
+    }
+}
@@ -1,14 +1,21 @@
+mod advertisement_utils;
 mod characteristic_utils;
 mod mac_extensions;
 mod mac_utils;
 pub mod peripheral_delegate;
 mod peripheral_manager;
 
+use super::PeripheralBackend;
 use crate::{
-    gatt::{peripheral_event::PeripheralEvent, service::Service},
+    adv::advertisement_data::AdvertisementData,
+    gatt::{
+        database::GattDatabase, notification::OversizePolicy, peripheral_event::PeripheralEvent,
+        service::Service,
+    },
     Error,
 };
 use peripheral_manager::PeripheralManager;
+use std::time::Duration;
 use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
@@ -16,29 +23,64 @@ pub struct Peripheral {
     peripheral_manager: PeripheralManager,
 }
 
-impl Peripheral {
-    pub async fn new(sender_tx: Sender<PeripheralEvent>) -> Result<Self, Error> {
-        let peripheral_manager = PeripheralManager::new(sender_tx).unwrap();
+impl PeripheralBackend for Peripheral {
+    async fn new(sender_tx: Sender<PeripheralEvent>) -> Result<Self, Error> {
+        let peripheral_manager = PeripheralManager::new(sender_tx)?;
         Ok(Peripheral { peripheral_manager })
     }
 
-    pub async fn is_powered(&mut self) -> Result<bool, Error> {
+    async fn is_powered(&mut self) -> Result<bool, Error> {
         return Ok(self.peripheral_manager.is_powered());
     }
 
-    pub async fn is_advertising(&mut self) -> Result<bool, Error> {
+    async fn is_advertising(&mut self) -> Result<bool, Error> {
         return Ok(self.peripheral_manager.is_advertising());
     }
 
-    pub async fn start_advertising(&mut self, name: &str, uuids: &[Uuid]) -> Result<(), Error> {
-        return Ok(self.peripheral_manager.start_advertising(name, uuids));
+    async fn start_advertising(&mut self, data: &AdvertisementData) -> Result<(), Error> {
+        return self.peripheral_manager.start_advertising(data);
     }
 
-    pub async fn stop_advertising(&mut self) -> Result<(), Error> {
+    async fn stop_advertising(&mut self) -> Result<(), Error> {
         return Ok(self.peripheral_manager.stop_advertising());
     }
 
-    pub async fn add_service(&mut self, service: &Service) -> Result<(), Error> {
+    async fn add_service(&mut self, service: &Service) -> Result<(), Error> {
+        service.check_descriptors()?;
+        characteristic_utils::check_descriptors(service)?;
         return Ok(self.peripheral_manager.add_service(service));
     }
+
+    fn database(&self) -> GattDatabase {
+        return self.peripheral_manager.database();
+    }
+
+    fn set_oversize_policy(&mut self, policy: OversizePolicy) {
+        self.peripheral_manager.set_oversize_policy(policy);
+    }
+
+    async fn update_characteristic(
+        &mut self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    ) -> Result<usize, Error> {
+        return self
+            .peripheral_manager
+            .update_characteristic(service, characteristic, value)
+            .await;
+    }
+
+    async fn indicate(
+        &mut self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        return self
+            .peripheral_manager
+            .indicate(service, characteristic, value, timeout)
+            .await;
+    }
 }
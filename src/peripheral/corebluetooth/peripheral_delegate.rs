@@ -1,17 +1,47 @@
-use crate::gatt::peripheral_event::PeripheralEvent;
+use crate::{
+    gatt::{
+        database::GattDatabase,
+        notification::{ClientConfiguration, DEFAULT_ATT_MTU},
+        peripheral_event::{PeripheralEvent, ReadResponse, WriteResponse},
+        prepared_write::{self, PreparedWrite},
+        read_cache::{self, ReadCache},
+    },
+    AttError,
+};
 
-use super::{mac_extensions::UuidHelper, mac_utils};
+use super::{
+    mac_extensions::{ClientExtension, UuidHelper},
+    mac_utils,
+};
 use objc2::{
     declare_class, msg_send_id, mutability, rc::Retained, runtime::AnyObject, ClassType,
     DeclaredClass,
 };
 use objc2_core_bluetooth::{
-    CBATTError, CBATTRequest, CBCentral, CBCharacteristic, CBManagerState, CBPeripheralManager,
-    CBPeripheralManagerDelegate, CBService,
+    CBATTError, CBATTRequest, CBCentral, CBCharacteristic, CBCharacteristicProperties,
+    CBManagerState, CBPeripheralManager, CBPeripheralManagerDelegate, CBService,
 };
-use objc2_foundation::{NSArray, NSData, NSError, NSObject, NSObjectProtocol};
-use std::{cell::RefCell, ffi::CString, fmt::Debug, sync::Arc};
-use tokio::sync::{mpsc::Sender, oneshot};
+use objc2_foundation::{NSArray, NSData, NSError, NSInteger, NSObject, NSObjectProtocol};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::CString,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{mpsc::Sender, oneshot, Notify};
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct DelegateIvars {
+    sender: Sender<PeripheralEvent>,
+    peripheral_manager: RefCell<Option<Retained<CBPeripheralManager>>>,
+    // Identifiers of subscribed centrals with their MTU, keyed by service and characteristic uuid
+    subscribers: Mutex<HashMap<(Uuid, Uuid), HashMap<String, u16>>>,
+    ready_to_update: Arc<Notify>,
+    reads: ReadCache,
+    database: GattDatabase,
+}
 
 declare_class!(
     #[derive(Debug)]
@@ -24,7 +54,7 @@ declare_class!(
     }
 
     impl DeclaredClass for PeripheralDelegate {
-        type Ivars = (Sender<PeripheralEvent>, RefCell<Option<Retained<CBPeripheralManager>>>);
+        type Ivars = DelegateIvars;
     }
 
     unsafe impl NSObjectProtocol for PeripheralDelegate {}
@@ -69,10 +99,29 @@ declare_class!(
                 if service.is_none() {
                     return;
                 }
+                let service_uuid = characteristic.service().unwrap().get_uuid();
+                let client = central.to_client();
+                self.ivars()
+                    .subscribers
+                    .lock()
+                    .unwrap()
+                    .entry((service_uuid, characteristic.get_uuid()))
+                    .or_default()
+                    .insert(client.id.clone(), client.mtu.unwrap_or(DEFAULT_ATT_MTU));
+                // CoreBluetooth hides the CCCD value, notifications are used whenever
+                // the characteristic supports them
+                let notify = CBCharacteristicProperties::CBCharacteristicPropertyNotify
+                    | CBCharacteristicProperties::CBCharacteristicPropertyNotifyEncryptionRequired;
+                let configuration = if characteristic.properties().intersects(notify) {
+                    ClientConfiguration::NOTIFY
+                } else {
+                    ClientConfiguration::INDICATE
+                };
                 self.send_event(PeripheralEvent::DidSubscribeToCharacteristic {
-                    client: central.identifier().to_string(),
-                    service: characteristic.service().unwrap().get_uuid(),
+                    client: Some(client),
+                    service: service_uuid,
                     characteristic: characteristic.get_uuid(),
+                    configuration,
                 });
             }
         }
@@ -88,13 +137,29 @@ declare_class!(
             if service.is_none() {
                 return;
             }
+            let service_uuid = characteristic.service().unwrap().get_uuid();
+            let client = central.to_client();
+            if let Some(centrals) = self
+                .ivars()
+                .subscribers
+                .lock()
+                .unwrap()
+                .get_mut(&(service_uuid, characteristic.get_uuid()))
+            {
+                centrals.remove(&client.id);
+            }
             self.send_event(PeripheralEvent::DidUnsubscribeFromCharacteristic {
-                client: central.identifier().to_string(),
-                service: characteristic.service().unwrap().get_uuid(),
+                client: Some(client),
+                service: service_uuid,
                 characteristic: characteristic.get_uuid(),
             });
         }}
 
+        #[method(peripheralManagerIsReadyToUpdateSubscribers:)]
+        fn delegate_peripheralmanagerisreadytoupdatesubscribers(&self, _: &CBPeripheralManager) {
+            self.ivars().ready_to_update.notify_one();
+        }
+
         #[method(peripheralManager:didReceiveReadRequest:)]
          fn delegate_peripheralmanager_didreceivereadrequest(
             &self,
@@ -106,20 +171,41 @@ declare_class!(
                 if service.is_none() {
                     return;
                 }
-                let central = request.central();
+                let client = request.central().to_client();
                 let characteristic = request.characteristic();
+                let service_uuid = characteristic.service().unwrap().get_uuid();
+                let characteristic_uuid = characteristic.get_uuid();
+                let offset = u16::try_from(request.offset()).unwrap_or(u16::MAX);
+                let reads = &self.ivars().reads;
+
+                if let Some(value) = self.ivars().database.get(service_uuid, characteristic_uuid) {
+                    self.respond_to_read(request, read_cache::slice(&value, offset));
+                    return;
+                }
+
+                // Follow-up reads of a long value are served from the cached value
+                if let Some(response) = reads.read(&client, service_uuid, characteristic_uuid, offset) {
+                    self.respond_to_read(request, response);
+                    return;
+                }
 
-                let (resp_tx, resp_rx) = oneshot::channel::<Vec<u8>>();
-                self.send_and_respond(
+                let (resp_tx, resp_rx) = oneshot::channel::<ReadResponse>();
+                let response = self.send_and_wait(
                     PeripheralEvent::DidReceiveReadRequest{
-                        client: central.identifier().to_string(),
-                        service: characteristic.service().unwrap().get_uuid(),
-                        characteristic: characteristic.get_uuid(),
+                        client: client.clone(),
+                        service: service_uuid,
+                        characteristic: characteristic_uuid,
+                        offset,
                         responder: resp_tx,
                     },
-                    request,
                     resp_rx,
                 );
+                let response = match response {
+                    Some(Ok(value)) => reads.store(&client, service_uuid, characteristic_uuid, offset, value),
+                    Some(Err(error)) => Err(error),
+                    None => Err(AttError::UnlikelyError),
+                };
+                self.respond_to_read(request, response);
             }
         }
 
@@ -129,28 +215,80 @@ declare_class!(
             _: &CBPeripheralManager,
             requests: &NSArray<CBATTRequest>,
         ){
+            let Some(first_request) = requests.first() else {
+                return;
+            };
+            let client = unsafe { first_request.central() }.to_client();
+
+            // Long and reliable writes arrive as one batch of chunks on Execute Write,
+            // which are merged into one write per characteristic
+            let mut queue: Vec<PreparedWrite> = vec![];
             for request in requests {
                 unsafe{
-                    let service = request.characteristic().service();
-                    if service.is_none() {
-                        return;
-                    }
+                    let characteristic = request.characteristic();
+                    let Some(service) = characteristic.service() else {
+                        continue;
+                    };
                     let mut value: Vec<u8> = Vec::new();
 
                     if let Some(ns_data) = request.value() {
                        value = ns_data.bytes().to_vec();
                     }
 
-                    let central = request.central();
-                    let characteristic = request.characteristic();
-                    self.send_event(PeripheralEvent::DidReceiveWriteRequest{
-                        client: central.identifier().to_string(),
-                        service: characteristic.service().unwrap().get_uuid(),
+                    queue.push(PreparedWrite {
+                        service: service.get_uuid(),
                         characteristic: characteristic.get_uuid(),
-                        value: value,
+                        offset: u16::try_from(request.offset()).unwrap_or(u16::MAX),
+                        value,
                     });
                 }
             }
+
+            let mut result = CBATTError::Success;
+            for write in prepared_write::execute(queue) {
+                let value = match write.value {
+                    Ok(value) => value,
+                    Err(error) => {
+                        result = error.to_cb_att_error();
+                        break;
+                    }
+                };
+
+                let (resp_tx, resp_rx) = oneshot::channel::<WriteResponse>();
+                let response = self.send_and_wait(
+                    PeripheralEvent::DidReceiveWriteRequest{
+                        client: client.clone(),
+                        service: write.service,
+                        characteristic: write.characteristic,
+                        value: value.clone(),
+                        responder: resp_tx,
+                    },
+                    resp_rx,
+                );
+
+                // A single failure rejects the whole batch
+                match response {
+                    Some(Ok(())) => self.ivars().database.store_written(
+                        write.service,
+                        write.characteristic,
+                        value,
+                    ),
+                    Some(Err(error)) => {
+                        result = error.to_cb_att_error();
+                        break;
+                    }
+                    None => {
+                        result = CBATTError::UnlikelyError;
+                        break;
+                    }
+                }
+            }
+
+            // CoreBluetooth expects one response for all requests, passed with the first one
+            unsafe {
+                self.get_peripheral_manager()
+                    .respondToRequest_withResult(first_request, result);
+            }
         }
     }
 );
@@ -158,11 +296,19 @@ declare_class!(
 impl PeripheralDelegate {
     pub fn new(
         sender: Sender<PeripheralEvent>,
+        database: GattDatabase,
     ) -> (
         Retained<CBPeripheralManager>,
         Arc<Retained<PeripheralDelegate>>,
     ) {
-        let this = PeripheralDelegate::alloc().set_ivars((sender, RefCell::new(None)));
+        let this = PeripheralDelegate::alloc().set_ivars(DelegateIvars {
+            sender,
+            peripheral_manager: RefCell::new(None),
+            subscribers: Mutex::new(HashMap::new()),
+            ready_to_update: Arc::new(Notify::new()),
+            reads: ReadCache::default(),
+            database,
+        });
         let delegate: Arc<Retained<PeripheralDelegate>> =
             Arc::new(unsafe { msg_send_id![super(this), init] });
         let label: CString = CString::new("CBqueue").unwrap();
@@ -178,7 +324,7 @@ impl PeripheralDelegate {
         // However, it creates a circular reference, which could potentially lead to memory leaks if not managed carefully
         delegate
             .ivars()
-            .1
+            .peripheral_manager
             .borrow_mut()
             .replace(peripheral_manager_delegate.clone());
 
@@ -186,11 +332,37 @@ impl PeripheralDelegate {
     }
 
     pub fn get_peripheral_manager(&self) -> Retained<CBPeripheralManager> {
-        return self.ivars().1.borrow().clone().unwrap();
+        return self.ivars().peripheral_manager.borrow().clone().unwrap();
+    }
+
+    pub fn subscriber_count(&self, service: Uuid, characteristic: Uuid) -> usize {
+        return self
+            .ivars()
+            .subscribers
+            .lock()
+            .unwrap()
+            .get(&(service, characteristic))
+            .map_or(0, |centrals| centrals.len());
+    }
+
+    /// Smallest MTU among the centrals subscribed to the characteristic
+    pub fn subscriber_mtu(&self, service: Uuid, characteristic: Uuid) -> Option<u16> {
+        return self
+            .ivars()
+            .subscribers
+            .lock()
+            .unwrap()
+            .get(&(service, characteristic))
+            .and_then(|centrals| centrals.values().min().copied());
+    }
+
+    /// Signaled once the transmit queue has room again after `updateValue` returned false
+    pub fn ready_to_update(&self) -> Arc<Notify> {
+        return self.ivars().ready_to_update.clone();
     }
 
     fn send_event(&self, event: PeripheralEvent) {
-        let sender = self.ivars().0.clone();
+        let sender = self.ivars().sender.clone();
         futures::executor::block_on(async {
             if let Err(e) = sender.send(event).await {
                 log::error!("Error sending delegate event: {}", e);
@@ -198,43 +370,49 @@ impl PeripheralDelegate {
         });
     }
 
-    fn send_and_respond(
-        &self,
-        event: PeripheralEvent,
-        request: &CBATTRequest,
-        resp_rx: oneshot::Receiver<Vec<u8>>,
-    ) {
-        let sender = self.ivars().0.clone();
+    fn send_and_wait<T>(&self, event: PeripheralEvent, resp_rx: oneshot::Receiver<T>) -> Option<T> {
+        let sender = self.ivars().sender.clone();
 
         futures::executor::block_on(async {
-            // Send to Listener
             if let Err(e) = sender.send(event).await {
                 log::error!("Error sending delegate event: {}", e);
-                return;
+                return None;
             }
+            resp_rx.await.ok()
+        })
+    }
 
-            // Wait for response
-            // TODO: Add timeout
-            let result = resp_rx.await;
-            unsafe {
-                if result.is_ok() {
-                    request.setValue(Some(&NSData::from_vec(result.unwrap())));
-                } else {
+    // CoreBluetooth expects the value to start at the offset of the request
+    fn respond_to_read(&self, request: &CBATTRequest, response: ReadResponse) {
+        unsafe {
+            let att_result = match response {
+                Ok(value) => {
+                    request.setValue(Some(&NSData::from_vec(value)));
+                    CBATTError::Success
+                }
+                Err(error) => {
                     request.setValue(None);
+                    error.to_cb_att_error()
                 }
+            };
 
-                // Update Manager
-                self.get_peripheral_manager()
-                    .respondToRequest_withResult(request, CBATTError::Success);
-            }
-        });
+            // Update Manager
+            self.get_peripheral_manager()
+                .respondToRequest_withResult(request, att_result);
+        }
+    }
+}
+
+impl AttError {
+    fn to_cb_att_error(self) -> CBATTError {
+        CBATTError(self.code() as NSInteger)
     }
 }
 
 impl Drop for PeripheralDelegate {
     fn drop(&mut self) {
         // Clear the reference to CBPeripheralManager, and Remove delegate
-        if let Some(manager) = self.ivars().1.borrow_mut().take() {
+        if let Some(manager) = self.ivars().peripheral_manager.borrow_mut().take() {
             unsafe {
                 manager.setDelegate(None);
                 log::debug!("Delegate removed")
@@ -1,43 +1,92 @@
+use super::advertisement_utils::parse_advertisement;
 use super::characteristic_utils::parse_characteristic;
 use super::mac_extensions::UuidExtension as _;
 use super::peripheral_delegate::PeripheralDelegate;
+use crate::adv::{
+    advertisement_data::AdvertisementData,
+    payload::{self, Layout},
+};
+use crate::gatt::database::{GattDatabase, ValueUpdate};
+use crate::gatt::notification::OversizePolicy;
 use crate::gatt::peripheral_event::PeripheralEvent;
 use crate::gatt::service::Service;
 use crate::Error;
-use objc2::{rc::Retained, runtime::AnyObject, ClassType};
+use objc2::{rc::Retained, ClassType};
 use objc2_core_bluetooth::{
-    CBAdvertisementDataLocalNameKey, CBAdvertisementDataServiceUUIDsKey, CBCharacteristic,
-    CBManager, CBManagerAuthorization, CBManagerState, CBMutableService, CBPeripheralManager,
+    CBCharacteristic, CBCharacteristicProperties, CBManager, CBManagerAuthorization,
+    CBManagerState, CBMutableCharacteristic, CBMutableService, CBPeripheralManager,
+};
+use objc2_foundation::{NSArray, NSData};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
-use objc2_foundation::{NSArray, NSDictionary, NSString};
-use std::sync::Arc;
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub struct PeripheralManager {
     cb_peripheral_manager: Retained<CBPeripheralManager>,
-    #[allow(dead_code)] // Keep peripheral_delegate to maintain delegate lifecycle
+    updater: Updater,
+    database: GattDatabase,
+}
+
+/// Sends values to subscribed centrals, shared with the thread which pushes
+/// values set in the database
+#[derive(Debug, Clone)]
+struct Updater {
+    cb_peripheral_manager: Retained<CBPeripheralManager>,
+    // Also keeps peripheral_delegate alive to maintain delegate lifecycle
     peripheral_delegate: Arc<Retained<PeripheralDelegate>>,
+    characteristics: Arc<Mutex<HashMap<(Uuid, Uuid), Retained<CBMutableCharacteristic>>>>,
+    oversize_policy: Arc<Mutex<OversizePolicy>>,
 }
 
+// CBPeripheralManager may be messaged from any thread, its delegate
+// callbacks run on their own dispatch queue
+unsafe impl Send for Updater {}
+unsafe impl Sync for Updater {}
+
+// Lets the futures of `PeripheralBackend` be Send, for the same reason as `Updater`
+unsafe impl Send for PeripheralManager {}
+unsafe impl Sync for PeripheralManager {}
+
 impl PeripheralManager {
     pub fn new(sender_tx: mpsc::Sender<PeripheralEvent>) -> Result<Self, Error> {
         if !is_authorized() {
             return Err(Error::from_type(crate::ErrorType::PermissionDenied));
         }
 
+        let (database, updates_rx) = GattDatabase::new();
         let result: (
             Retained<CBPeripheralManager>,
             Arc<Retained<PeripheralDelegate>>,
-        ) = PeripheralDelegate::new(sender_tx);
+        ) = PeripheralDelegate::new(sender_tx, database.clone());
+
+        let updater = Updater {
+            cb_peripheral_manager: result.0.clone(),
+            peripheral_delegate: result.1,
+            characteristics: Arc::new(Mutex::new(HashMap::new())),
+            oversize_policy: Arc::new(Mutex::new(OversizePolicy::default())),
+        };
+        push_value_updates(updates_rx, updater.clone());
 
         Ok(Self {
             cb_peripheral_manager: result.0,
-            peripheral_delegate: result.1,
+            updater,
+            database,
         })
     }
 
+    pub fn database(self: &Self) -> GattDatabase {
+        return self.database.clone();
+    }
+
+    pub fn set_oversize_policy(self: &Self, policy: OversizePolicy) {
+        *self.updater.oversize_policy.lock().unwrap() = policy;
+    }
+
     pub fn is_powered(self: &Self) -> bool {
         unsafe {
             let state = self.cb_peripheral_manager.state();
@@ -45,27 +94,16 @@ impl PeripheralManager {
         }
     }
 
-    pub fn start_advertising(self: &Self, name: &str, uuids: &[Uuid]) {
-        let mut keys: Vec<&NSString> = vec![];
-        let mut objects: Vec<Retained<AnyObject>> = vec![];
-
-        unsafe {
-            keys.push(CBAdvertisementDataLocalNameKey);
-            objects.push(Retained::cast(NSString::from_str(name)));
-
-            keys.push(CBAdvertisementDataServiceUUIDsKey);
-            objects.push(Retained::cast(NSArray::from_vec(
-                uuids.iter().map(|u| u.to_cbuuid()).collect(),
-            )));
-        }
-
-        let advertising_data: Retained<NSDictionary<NSString, AnyObject>> =
-            NSDictionary::from_vec(&keys, objects);
+    pub fn start_advertising(self: &Self, data: &AdvertisementData) -> Result<(), Error> {
+        data.check_parameters()?;
+        payload::check(data, Layout::NameInScanResponse)?;
+        let advertising_data = parse_advertisement(data)?;
 
         unsafe {
             self.cb_peripheral_manager
                 .startAdvertising(Some(&advertising_data));
         }
+        return Ok(());
     }
 
     pub fn stop_advertising(self: &Self) {
@@ -78,15 +116,19 @@ impl PeripheralManager {
         unsafe { self.cb_peripheral_manager.isAdvertising() }
     }
 
-    // Peripheral with cache value must only have Read permission, else it will crash
-    // TODO: throw proper error, or catch Objc errors
-    pub fn add_service(self: &Self, service: &Service) {
+    pub fn add_service(self: &mut Self, service: &Service) {
+        self.database.add_service(service);
         unsafe {
-            let characteristics: Vec<Retained<CBCharacteristic>> = service
-                .characteristics
-                .iter()
-                .map(|characteristic| parse_characteristic(characteristic))
-                .collect();
+            let mut characteristics: Vec<Retained<CBCharacteristic>> = vec![];
+            for characteristic in service.characteristics.iter() {
+                let mutable_char = parse_characteristic(characteristic);
+                self.updater
+                    .characteristics
+                    .lock()
+                    .unwrap()
+                    .insert((service.uuid, characteristic.uuid), mutable_char.clone());
+                characteristics.push(Retained::into_super(mutable_char));
+            }
 
             let mutable_service: Retained<CBMutableService> =
                 CBMutableService::initWithType_primary(
@@ -103,6 +145,172 @@ impl PeripheralManager {
             self.cb_peripheral_manager.addService(&mutable_service);
         }
     }
+
+    pub async fn update_characteristic(
+        self: &Self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    ) -> Result<usize, Error> {
+        return self
+            .updater
+            .update_characteristic(service, characteristic, value)
+            .await;
+    }
+
+    pub async fn indicate(
+        self: &Self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        return self
+            .updater
+            .indicate(service, characteristic, value, timeout)
+            .await;
+    }
+}
+
+impl Drop for PeripheralManager {
+    fn drop(&mut self) {
+        // Ends the push thread, the delegate holds on to a clone of the database
+        self.database.close();
+    }
+}
+
+impl Updater {
+    async fn update_characteristic(
+        self: &Self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    ) -> Result<usize, Error> {
+        let known = self
+            .characteristics
+            .lock()
+            .unwrap()
+            .contains_key(&(service, characteristic));
+        if !known {
+            return Err(characteristic_not_found());
+        }
+
+        let subscribers = self
+            .peripheral_delegate
+            .subscriber_count(service, characteristic);
+        let Some(mtu) = self
+            .peripheral_delegate
+            .subscriber_mtu(service, characteristic)
+        else {
+            return Ok(0);
+        };
+
+        // CoreBluetooth truncates values to the smallest payload among all subscribers
+        let oversize_policy = *self.oversize_policy.lock().unwrap();
+        let payloads = oversize_policy.payloads(value, mtu)?;
+        let ready_to_update = self.peripheral_delegate.ready_to_update();
+        for payload in payloads {
+            while !self.send_payload(service, characteristic, &payload)? {
+                // Transmit queue is full, retry once CoreBluetooth reports it is ready again
+                ready_to_update.notified().await;
+            }
+        }
+        return Ok(subscribers);
+    }
+
+    /// Hand a payload to CoreBluetooth, `false` when its transmit queue is full.
+    /// Objective-C objects are not Send, so none is held across an await
+    fn send_payload(
+        self: &Self,
+        service: Uuid,
+        characteristic: Uuid,
+        payload: &[u8],
+    ) -> Result<bool, Error> {
+        let characteristics = self.characteristics.lock().unwrap();
+        let Some(mutable_char) = characteristics.get(&(service, characteristic)) else {
+            return Err(characteristic_not_found());
+        };
+        let data = NSData::from_vec(payload.to_vec());
+        let sent = unsafe {
+            self.cb_peripheral_manager
+                .updateValue_forCharacteristic_onSubscribedCentrals(&data, mutable_char, None)
+        };
+        return Ok(sent);
+    }
+
+    // CoreBluetooth does not report Handle Value Confirmations, an indication
+    // counts as delivered once it was accepted into the transmit queue
+    async fn indicate(
+        self: &Self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        if let Some(mutable_char) = self
+            .characteristics
+            .lock()
+            .unwrap()
+            .get(&(service, characteristic))
+        {
+            let properties = unsafe { mutable_char.properties() };
+            let indicate = CBCharacteristicProperties::CBCharacteristicPropertyIndicate
+                | CBCharacteristicProperties::CBCharacteristicPropertyIndicateEncryptionRequired;
+            if !properties.intersects(indicate) {
+                return Err(Error::new(
+                    "NotSupported",
+                    "Characteristic does not support indications",
+                    crate::ErrorType::CoreBluetooth,
+                ));
+            }
+        }
+
+        let result = tokio::time::timeout(
+            timeout,
+            self.update_characteristic(service, characteristic, value),
+        )
+        .await;
+
+        return match result {
+            Ok(Ok(0)) => Err(Error::new(
+                "NotSubscribed",
+                "No central is subscribed to this characteristic",
+                crate::ErrorType::CoreBluetooth,
+            )),
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(Error::new(
+                "IndicationTimeout",
+                "Indication could not be sent in time",
+                crate::ErrorType::CoreBluetooth,
+            )),
+        };
+    }
+}
+
+/// Send values stored in the database to the subscribers of their characteristic,
+/// on a thread of its own as the delegate callbacks block their dispatch queue
+fn push_value_updates(mut updates_rx: UnboundedReceiver<ValueUpdate>, updater: Updater) {
+    std::thread::spawn(move || {
+        futures::executor::block_on(async move {
+            while let Some(update) = updates_rx.recv().await {
+                if let Err(err) = updater
+                    .update_characteristic(update.service, update.characteristic, update.value)
+                    .await
+                {
+                    log::warn!("Error sending stored value: {}", err);
+                }
+            }
+        });
+    });
+}
+
+fn characteristic_not_found() -> Error {
+    return Error::new(
+        "CharacteristicNotFound",
+        "Characteristic is not part of any added service",
+        crate::ErrorType::CoreBluetooth,
+    );
 }
 
 pub fn is_authorized() -> bool {
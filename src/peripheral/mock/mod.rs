@@ -0,0 +1,396 @@
+mod virtual_central;
+
+use super::PeripheralBackend;
+use crate::{
+    adv::{
+        advertisement_data::AdvertisementData,
+        payload::{self, Layout},
+    },
+    gatt::{
+        characteristic::Characteristic,
+        client::Client,
+        database::{GattDatabase, ValueUpdate},
+        notification::OversizePolicy,
+        peripheral_event::PeripheralEvent,
+        properties::CharacteristicProperty,
+        read_cache::ReadCache,
+        service::Service,
+    },
+    Error, ErrorType,
+};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc::{Sender, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+use uuid::Uuid;
+
+pub use virtual_central::VirtualCentral;
+
+/// State of the simulated adapter, shared between the peripheral and its handles
+#[derive(Debug)]
+pub(crate) struct MockState {
+    pub(crate) powered: bool,
+    pub(crate) advertising: bool,
+    pub(crate) advertisement: Option<AdvertisementData>,
+    pub(crate) services: Vec<Service>,
+    pub(crate) connected: HashSet<String>,
+    pub(crate) subscriptions: Vec<Subscription>,
+    pub(crate) reads: ReadCache,
+    pub(crate) database: GattDatabase,
+    pub(crate) oversize_policy: OversizePolicy,
+}
+
+impl MockState {
+    pub(crate) fn find_characteristic(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+    ) -> Option<Characteristic> {
+        self.services
+            .iter()
+            .filter(|s| s.uuid == service)
+            .flat_map(|s| s.characteristics.iter())
+            .find(|c| c.uuid == characteristic)
+            .cloned()
+    }
+
+    /// Send a value to the subscribers of the characteristic, returns how many received it
+    pub(crate) fn push(
+        &mut self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    ) -> Result<usize, Error> {
+        if self.find_characteristic(service, characteristic).is_none() {
+            return Err(mock_error("InvalidHandle", "Characteristic not found"));
+        }
+        let Some(mtu) = self
+            .subscriptions
+            .iter()
+            .filter(|s| s.service == service && s.characteristic == characteristic)
+            .map(|s| s.mtu)
+            .min()
+        else {
+            return Ok(0);
+        };
+        let payloads = self.oversize_policy.payloads(value, mtu)?;
+
+        let mut delivered = 0;
+        self.subscriptions.retain(|subscription| {
+            if subscription.service != service || subscription.characteristic != characteristic {
+                return true;
+            }
+            let sent = payloads
+                .iter()
+                .all(|payload| subscription.sender.send(payload.clone()).is_ok());
+            if sent {
+                delivered += 1;
+            }
+            sent
+        });
+        Ok(delivered)
+    }
+}
+
+/// Notification channel of a virtual central subscribed to a characteristic
+#[derive(Debug)]
+pub(crate) struct Subscription {
+    pub(crate) client: String,
+    pub(crate) service: Uuid,
+    pub(crate) characteristic: Uuid,
+    pub(crate) mtu: u16,
+    pub(crate) sender: UnboundedSender<Vec<u8>>,
+}
+
+impl Subscription {
+    pub(crate) fn matches(&self, client: &str, service: Uuid, characteristic: Uuid) -> bool {
+        self.client == client && self.service == service && self.characteristic == characteristic
+    }
+}
+
+/// In-memory peripheral which needs no Bluetooth stack, useful to test GATT server logic
+#[derive(Debug)]
+pub struct Peripheral {
+    state: Arc<Mutex<MockState>>,
+    sender_tx: Sender<PeripheralEvent>,
+    database: GattDatabase,
+    value_updates: JoinHandle<()>,
+}
+
+impl PeripheralBackend for Peripheral {
+    async fn new(sender_tx: Sender<PeripheralEvent>) -> Result<Self, Error> {
+        let (database, updates_rx) = GattDatabase::new();
+        let state = Arc::new(Mutex::new(MockState {
+            powered: true,
+            advertising: false,
+            advertisement: None,
+            services: Vec::new(),
+            connected: HashSet::new(),
+            subscriptions: Vec::new(),
+            reads: ReadCache::default(),
+            database: database.clone(),
+            oversize_policy: OversizePolicy::default(),
+        }));
+        let peripheral = Peripheral {
+            state: state.clone(),
+            sender_tx,
+            database,
+            value_updates: tokio::spawn(push_value_updates(updates_rx, state)),
+        };
+        peripheral
+            .send_event(PeripheralEvent::DidUpdateState { is_powered: true })
+            .await;
+        Ok(peripheral)
+    }
+
+    async fn is_powered(&mut self) -> Result<bool, Error> {
+        Ok(self.state().powered)
+    }
+
+    async fn is_advertising(&mut self) -> Result<bool, Error> {
+        Ok(self.state().advertising)
+    }
+
+    async fn start_advertising(&mut self, data: &AdvertisementData) -> Result<(), Error> {
+        data.check_parameters()?;
+        payload::check(data, Layout::NameInScanResponse)?;
+        {
+            let mut state = self.state();
+            if !state.powered {
+                return Err(not_powered());
+            }
+            state.advertising = true;
+            state.advertisement = Some(data.clone());
+        }
+        self.send_event(PeripheralEvent::DidStartAdvertising { error: None })
+            .await;
+        Ok(())
+    }
+
+    async fn stop_advertising(&mut self) -> Result<(), Error> {
+        let mut state = self.state();
+        state.advertising = false;
+        state.advertisement = None;
+        Ok(())
+    }
+
+    async fn add_service(&mut self, service: &Service) -> Result<(), Error> {
+        service.check_descriptors()?;
+        self.state().services.push(service.clone());
+        self.database.add_service(service);
+        self.send_event(PeripheralEvent::DidAddService {
+            service: service.uuid,
+            error: None,
+        })
+        .await;
+        Ok(())
+    }
+
+    fn database(&self) -> GattDatabase {
+        self.database.clone()
+    }
+
+    fn set_oversize_policy(&mut self, policy: OversizePolicy) {
+        self.state().oversize_policy = policy;
+    }
+
+    async fn update_characteristic(
+        &mut self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    ) -> Result<usize, Error> {
+        self.state().push(service, characteristic, value)
+    }
+
+    /// Virtual centrals confirm an indication as soon as it is queued on their channel
+    async fn indicate(
+        &mut self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+        _timeout: Duration,
+    ) -> Result<(), Error> {
+        let supported = self
+            .state()
+            .find_characteristic(service, characteristic)
+            .map(|c| {
+                c.properties.contains(&CharacteristicProperty::Indicate)
+                    || c.properties
+                        .contains(&CharacteristicProperty::IndicateEncryptionRequired)
+            });
+        if supported == Some(false) {
+            return Err(mock_error(
+                "NotSupported",
+                "Characteristic does not support indications",
+            ));
+        }
+
+        let subscribers = self
+            .state()
+            .subscriptions
+            .iter()
+            .filter(|s| s.service == service && s.characteristic == characteristic)
+            .count();
+        if subscribers == 0 {
+            return Err(mock_error(
+                "NotSubscribed",
+                "No central is subscribed to this characteristic",
+            ));
+        }
+
+        let delivered = self
+            .update_characteristic(service, characteristic, value)
+            .await?;
+        if delivered < subscribers {
+            return Err(mock_error(
+                "IndicationUnconfirmed",
+                "A central disconnected before confirming the indication",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Peripheral {
+    /// Simulate the adapter being switched on or off, powering off stops advertising
+    /// and drops every connected central
+    pub async fn set_powered(&mut self, powered: bool) {
+        let dropped: Vec<String> = {
+            let mut state = self.state();
+            if state.powered == powered {
+                return;
+            }
+            state.powered = powered;
+            if powered {
+                vec![]
+            } else {
+                state.advertising = false;
+                state.subscriptions.clear();
+                state.connected.drain().collect()
+            }
+        };
+        for client in dropped {
+            self.send_event(PeripheralEvent::DidDisconnect {
+                client: Client::new(client),
+                reason: Some("Adapter powered off".to_string()),
+            })
+            .await;
+        }
+        self.send_event(PeripheralEvent::DidUpdateState {
+            is_powered: powered,
+        })
+        .await;
+    }
+
+    /// Create a simulated central which reports `id` as its client identifier
+    pub fn virtual_central(&self, id: &str) -> VirtualCentral {
+        VirtualCentral::new(id.to_string(), self.state.clone(), self.sender_tx.clone())
+    }
+
+    /// Services registered with `add_service`
+    pub fn services(&self) -> Vec<Service> {
+        self.state().services.clone()
+    }
+
+    /// Content of the running advertisement
+    pub fn advertisement(&self) -> Option<AdvertisementData> {
+        let state = self.state();
+        if !state.advertising {
+            return None;
+        }
+        state.advertisement.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    async fn send_event(&self, event: PeripheralEvent) {
+        if let Err(err) = self.sender_tx.send(event).await {
+            log::error!("Error sending mock event: {:?}", err);
+        }
+    }
+}
+
+impl Drop for Peripheral {
+    fn drop(&mut self) {
+        self.database.close();
+        self.value_updates.abort();
+    }
+}
+
+/// Send values stored in the database to the virtual centrals subscribed to them
+async fn push_value_updates(
+    mut updates_rx: UnboundedReceiver<ValueUpdate>,
+    state: Arc<Mutex<MockState>>,
+) {
+    while let Some(update) = updates_rx.recv().await {
+        let result =
+            state
+                .lock()
+                .unwrap()
+                .push(update.service, update.characteristic, update.value);
+        if let Err(err) = result {
+            log::warn!("Error sending stored value: {}", err);
+        }
+    }
+}
+
+fn not_powered() -> Error {
+    mock_error("NotPowered", "Mock adapter is powered off")
+}
+
+pub(crate) fn mock_error(name: &str, description: &str) -> Error {
+    Error::new(name, description, ErrorType::Mock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adv::advertisement_data::LocalName;
+    use std::collections::BTreeMap;
+    use tokio::sync::mpsc::channel;
+
+    #[tokio::test]
+    async fn advertisement_which_does_not_fit_is_rejected() {
+        let (sender_tx, mut receiver_rx) = channel(16);
+        let mut peripheral = Peripheral::new(sender_tx).await.unwrap();
+        assert!(receiver_rx.try_recv().is_ok());
+
+        // Flags (3) and manufacturer data (24) leave too little room for a second entry,
+        // which must not overflow into the scan response
+        let data = AdvertisementData {
+            manufacturer_data: BTreeMap::from([(0x004C, vec![0; 20]), (0x0059, vec![0; 4])]),
+            ..Default::default()
+        };
+        let err = peripheral.start_advertising(&data).await.unwrap_err();
+        assert!(err.to_string().contains("AdvertisementTooLarge"));
+        assert!(!peripheral.is_advertising().await.unwrap());
+        assert!(receiver_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn local_name_goes_into_the_scan_response() {
+        let (sender_tx, mut receiver_rx) = channel(16);
+        let mut peripheral = Peripheral::new(sender_tx).await.unwrap();
+        assert!(receiver_rx.try_recv().is_ok());
+
+        let data = AdvertisementData {
+            manufacturer_data: BTreeMap::from([(0x004C, vec![0; 24])]),
+            local_name: Some(LocalName::Complete("A name of twenty bytes".to_string())),
+            ..Default::default()
+        };
+        assert!(peripheral.start_advertising(&data).await.is_ok());
+        assert!(peripheral.is_advertising().await.unwrap());
+        assert!(matches!(
+            receiver_rx.try_recv(),
+            Ok(PeripheralEvent::DidStartAdvertising { error: None })
+        ));
+    }
+}
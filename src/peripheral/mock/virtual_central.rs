@@ -0,0 +1,449 @@
+use super::{mock_error, MockState, Subscription};
+use crate::{
+    gatt::{
+        characteristic::Characteristic,
+        client::Client,
+        notification::{ClientConfiguration, DEFAULT_ATT_MTU},
+        peripheral_event::{PeripheralEvent, ReadResponse, WriteResponse},
+        prepared_write::{self, PreparedWrite},
+        properties::CharacteristicProperty,
+        read_cache::slice,
+        service::Service,
+    },
+    AttError, Error,
+};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::{
+    mpsc::{self, Sender, UnboundedReceiver},
+    oneshot,
+};
+use uuid::Uuid;
+
+/// Simulated central, drives the mock peripheral through the same events as real traffic
+#[derive(Debug)]
+pub struct VirtualCentral {
+    client: Client,
+    state: Arc<Mutex<MockState>>,
+    sender_tx: Sender<PeripheralEvent>,
+    prepared: Vec<PreparedWrite>,
+}
+
+impl VirtualCentral {
+    pub(crate) fn new(
+        id: String,
+        state: Arc<Mutex<MockState>>,
+        sender_tx: Sender<PeripheralEvent>,
+    ) -> Self {
+        VirtualCentral {
+            client: Client {
+                mtu: Some(DEFAULT_ATT_MTU),
+                ..Client::new(id)
+            },
+            state,
+            sender_tx,
+            prepared: Vec::new(),
+        }
+    }
+
+    /// Identifier of the client reported in peripheral events
+    pub fn id(&self) -> &str {
+        &self.client.id
+    }
+
+    /// Client reported in peripheral events
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Simulate an MTU exchange, applies to later requests and to existing subscriptions
+    pub fn set_mtu(&mut self, mtu: u16) {
+        self.client.mtu = Some(mtu);
+        for subscription in self.state().subscriptions.iter_mut() {
+            if subscription.client == self.client.id {
+                subscription.mtu = mtu;
+            }
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state().connected.contains(&self.client.id)
+    }
+
+    /// Connect to the peripheral, which must be powered and advertising
+    pub async fn connect(&mut self) -> Result<(), Error> {
+        {
+            let mut state = self.state();
+            if !state.advertising {
+                return Err(mock_error(
+                    "NotAdvertising",
+                    "Peripheral is not advertising",
+                ));
+            }
+            if !state.connected.insert(self.client.id.clone()) {
+                return Ok(());
+            }
+        }
+        self.send_event(PeripheralEvent::DidConnect {
+            client: self.client.clone(),
+        })
+        .await
+    }
+
+    /// Disconnect from the peripheral, dropping all subscriptions of this central
+    pub async fn disconnect(&mut self) -> Result<(), Error> {
+        if self.is_connected() {
+            self.cancel_prepared_writes().await?;
+        }
+        let subscriptions: Vec<Subscription> = {
+            let mut state = self.state();
+            if !state.connected.remove(&self.client.id) {
+                return Ok(());
+            }
+            let (own, others) = state
+                .subscriptions
+                .drain(..)
+                .partition(|subscription| subscription.client == self.client.id);
+            state.subscriptions = others;
+            state.reads.forget(&self.client);
+            own
+        };
+        for subscription in subscriptions {
+            self.send_event(PeripheralEvent::DidUnsubscribeFromCharacteristic {
+                client: Some(self.client.clone()),
+                service: subscription.service,
+                characteristic: subscription.characteristic,
+            })
+            .await?;
+        }
+        self.send_event(PeripheralEvent::DidDisconnect {
+            client: self.client.clone(),
+            reason: Some("Remote user terminated connection".to_string()),
+        })
+        .await
+    }
+
+    pub async fn discover_services(&self) -> Result<Vec<Service>, Error> {
+        self.ensure_connected()?;
+        Ok(self.state().services.clone())
+    }
+
+    /// Read the complete value, long values take a Read Blob request per MTU - 1 bytes
+    pub async fn read(&self, service: Uuid, characteristic: Uuid) -> Result<Vec<u8>, Error> {
+        let chunk_len = self.chunk_len();
+        let mut value = vec![];
+        loop {
+            let offset = u16::try_from(value.len())
+                .map_err(|_| mock_error("InvalidOffset", "Value exceeds the maximum offset"))?;
+            let chunk = self.read_at(service, characteristic, offset).await?;
+            let done = chunk.len() < chunk_len;
+            value.extend(chunk);
+            if done {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Issue a single read request, a Read Blob request when `offset` is not 0.
+    /// Like a real stack, at most MTU - 1 bytes are returned
+    pub async fn read_at(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        offset: u16,
+    ) -> Result<Vec<u8>, Error> {
+        self.characteristic_with(service, characteristic, &[CharacteristicProperty::Read])?;
+
+        let (reads, stored_value) = {
+            let state = self.state();
+            (
+                state.reads.clone(),
+                state.database.get(service, characteristic),
+            )
+        };
+        let cached = match stored_value {
+            Some(value) => Some(slice(&value, offset)),
+            None => reads.read(&self.client, service, characteristic, offset),
+        };
+        let response = match cached {
+            Some(response) => response,
+            None => {
+                let (resp_tx, resp_rx) = oneshot::channel::<ReadResponse>();
+                self.send_event(PeripheralEvent::DidReceiveReadRequest {
+                    client: self.client.clone(),
+                    service,
+                    characteristic,
+                    offset,
+                    responder: resp_tx,
+                })
+                .await?;
+
+                match resp_rx.await {
+                    Ok(Ok(value)) => {
+                        reads.store(&self.client, service, characteristic, offset, value)
+                    }
+                    Ok(Err(error)) => Err(error),
+                    Err(_) => {
+                        return Err(mock_error("NoResponse", "Read request was not answered"))
+                    }
+                }
+            }
+        };
+
+        let mut value = response.map_err(att_error)?;
+        value.truncate(self.chunk_len());
+        Ok(value)
+    }
+
+    pub async fn write(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.characteristic_with(service, characteristic, &[CharacteristicProperty::Write])?;
+        let resp_rx = self
+            .send_write(service, characteristic, value.clone())
+            .await?;
+
+        match resp_rx.await {
+            Ok(Ok(())) => {
+                self.state()
+                    .database
+                    .store_written(service, characteristic, value);
+                Ok(())
+            }
+            Ok(Err(error)) => Err(att_error(error)),
+            Err(_) => Err(mock_error("NoResponse", "Write request was not answered")),
+        }
+    }
+
+    pub async fn write_without_response(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.characteristic_with(
+            service,
+            characteristic,
+            &[CharacteristicProperty::WriteWithoutResponse],
+        )?;
+        // Commands are not acknowledged, the central does not wait for the application
+        let resp_rx = self
+            .send_write(service, characteristic, value.clone())
+            .await?;
+        let database = self.state().database.clone();
+        tokio::spawn(async move {
+            if let Ok(Ok(())) = resp_rx.await {
+                database.store_written(service, characteristic, value);
+            }
+        });
+        Ok(())
+    }
+
+    /// Queue a chunk of a long write, nothing reaches the application before `execute_write`
+    pub fn prepare_write(
+        &mut self,
+        service: Uuid,
+        characteristic: Uuid,
+        offset: u16,
+        value: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.characteristic_with(service, characteristic, &[CharacteristicProperty::Write])?;
+        self.prepared.push(PreparedWrite {
+            service,
+            characteristic,
+            offset,
+            value,
+        });
+        Ok(())
+    }
+
+    /// Execute the queued writes, the application receives one write per characteristic
+    /// and the first rejection fails the rest of the queue
+    pub async fn execute_write(&mut self) -> Result<(), Error> {
+        self.ensure_connected()?;
+        for write in prepared_write::execute(std::mem::take(&mut self.prepared)) {
+            let value = write.value.map_err(att_error)?;
+            let resp_rx = self
+                .send_write(write.service, write.characteristic, value.clone())
+                .await?;
+            match resp_rx.await {
+                Ok(Ok(())) => {
+                    self.state()
+                        .database
+                        .store_written(write.service, write.characteristic, value)
+                }
+                Ok(Err(error)) => return Err(att_error(error)),
+                Err(_) => return Err(mock_error("NoResponse", "Write request was not answered")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Discard the queued writes
+    pub async fn cancel_write(&mut self) -> Result<(), Error> {
+        self.ensure_connected()?;
+        self.cancel_prepared_writes().await
+    }
+
+    /// Subscribe to a characteristic, values pushed by the peripheral arrive on the returned receiver.
+    /// Like most centrals it enables notifications when supported, indications otherwise
+    pub async fn subscribe(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+    ) -> Result<UnboundedReceiver<Vec<u8>>, Error> {
+        let found = self.characteristic_with(
+            service,
+            characteristic,
+            &[
+                CharacteristicProperty::Notify,
+                CharacteristicProperty::NotifyEncryptionRequired,
+                CharacteristicProperty::Indicate,
+                CharacteristicProperty::IndicateEncryptionRequired,
+            ],
+        )?;
+
+        let notify = found.properties.iter().any(|property| {
+            matches!(
+                property,
+                CharacteristicProperty::Notify | CharacteristicProperty::NotifyEncryptionRequired
+            )
+        });
+        let configuration = if notify {
+            ClientConfiguration::NOTIFY
+        } else {
+            ClientConfiguration::INDICATE
+        };
+
+        let (value_tx, value_rx) = mpsc::unbounded_channel::<Vec<u8>>();
+        {
+            let mut state = self.state();
+            state.subscriptions.retain(|subscription| {
+                !subscription.matches(&self.client.id, service, characteristic)
+            });
+            state.subscriptions.push(Subscription {
+                client: self.client.id.clone(),
+                service,
+                characteristic,
+                mtu: self.client.mtu.unwrap_or(DEFAULT_ATT_MTU),
+                sender: value_tx,
+            });
+        }
+
+        self.send_event(PeripheralEvent::DidSubscribeToCharacteristic {
+            client: Some(self.client.clone()),
+            service,
+            characteristic,
+            configuration,
+        })
+        .await?;
+        Ok(value_rx)
+    }
+
+    pub async fn unsubscribe(&self, service: Uuid, characteristic: Uuid) -> Result<(), Error> {
+        self.ensure_connected()?;
+        {
+            let mut state = self.state();
+            let count = state.subscriptions.len();
+            state.subscriptions.retain(|subscription| {
+                !subscription.matches(&self.client.id, service, characteristic)
+            });
+            if state.subscriptions.len() == count {
+                return Err(mock_error(
+                    "NotSubscribed",
+                    "Characteristic is not subscribed",
+                ));
+            }
+        }
+
+        self.send_event(PeripheralEvent::DidUnsubscribeFromCharacteristic {
+            client: Some(self.client.clone()),
+            service,
+            characteristic,
+        })
+        .await
+    }
+
+    fn chunk_len(&self) -> usize {
+        usize::from(self.client.mtu.unwrap_or(DEFAULT_ATT_MTU).saturating_sub(1))
+    }
+
+    async fn cancel_prepared_writes(&mut self) -> Result<(), Error> {
+        for write in prepared_write::execute(std::mem::take(&mut self.prepared)) {
+            self.send_event(PeripheralEvent::DidCancelPreparedWrite {
+                client: self.client.clone(),
+                service: write.service,
+                characteristic: write.characteristic,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn send_write(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    ) -> Result<oneshot::Receiver<WriteResponse>, Error> {
+        let (resp_tx, resp_rx) = oneshot::channel::<WriteResponse>();
+        self.send_event(PeripheralEvent::DidReceiveWriteRequest {
+            client: self.client.clone(),
+            service,
+            characteristic,
+            value,
+            responder: resp_tx,
+        })
+        .await?;
+        Ok(resp_rx)
+    }
+
+    /// Find a characteristic which supports any of the given properties
+    fn characteristic_with(
+        &self,
+        service: Uuid,
+        characteristic: Uuid,
+        properties: &[CharacteristicProperty],
+    ) -> Result<Characteristic, Error> {
+        self.ensure_connected()?;
+        let characteristic = self
+            .state()
+            .find_characteristic(service, characteristic)
+            .ok_or_else(|| mock_error("InvalidHandle", "Characteristic not found"))?;
+
+        if !properties
+            .iter()
+            .any(|property| characteristic.properties.contains(property))
+        {
+            return Err(mock_error(
+                "NotSupported",
+                "Characteristic does not support this operation",
+            ));
+        }
+        Ok(characteristic)
+    }
+
+    fn ensure_connected(&self) -> Result<(), Error> {
+        if !self.is_connected() {
+            return Err(mock_error("NotConnected", "Central is not connected"));
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    async fn send_event(&self, event: PeripheralEvent) -> Result<(), Error> {
+        self.sender_tx
+            .send(event)
+            .await
+            .map_err(|_| mock_error("ChannelClosed", "Peripheral event receiver was dropped"))
+    }
+}
+
+fn att_error(error: AttError) -> Error {
+    mock_error("AttError", &format!("Request failed with {}", error))
+}
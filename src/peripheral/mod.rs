@@ -1,3 +1,15 @@
+use crate::{
+    adv::advertisement_data::AdvertisementData,
+    gatt::{
+        database::GattDatabase, notification::OversizePolicy, peripheral_event::PeripheralEvent,
+        service::Service,
+    },
+    Error,
+};
+use std::{future::Future, time::Duration};
+use tokio::sync::mpsc::Sender;
+use uuid::Uuid;
+
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 mod corebluetooth;
 #[cfg(any(target_os = "macos", target_os = "ios"))]
@@ -7,3 +19,68 @@ pub use self::corebluetooth::Peripheral;
 mod bluez;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub use self::bluez::Peripheral;
+
+#[cfg(feature = "mock")]
+pub mod mock;
+
+/// Common api implemented by every peripheral backend, events are delivered on `sender_tx`.
+/// The returned futures are `Send`, so calls can be spawned onto a multi-threaded runtime
+pub trait PeripheralBackend: Sized + Send {
+    fn new(sender_tx: Sender<PeripheralEvent>) -> impl Future<Output = Result<Self, Error>> + Send;
+
+    fn is_powered(&mut self) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    fn is_advertising(&mut self) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Fails with an `Error` if the platform cannot send a field of `data`, if a timing or
+    /// power value is out of range, or if a field does not fit into the advertising data.
+    /// BlueZ and CoreBluetooth only put the local name into the scan response,
+    /// see [`crate::adv::payload::Layout::NameInScanResponse`]
+    fn start_advertising(
+        &mut self,
+        data: &AdvertisementData,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    fn stop_advertising(&mut self) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// The stack manages the Client and Server Characteristic Configuration descriptors,
+    /// every backend rejects a service which declares 0x2902 or 0x2903 itself. Beyond that:
+    /// - BlueZ rejects `NotifyEncryptionRequired` and `IndicateEncryptionRequired`, which it
+    ///   cannot enforce. It publishes 0x2900 from the characteristic flags, and rejects one
+    ///   without a two byte value or with reliable write on a characteristic without `Write`
+    /// - CoreBluetooth rejects every descriptor other than 0x2901 and 0x2904,
+    ///   and those without a value or not readable
+    /// - The mock accepts every other descriptor
+    fn add_service(&mut self, service: &Service) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Store holding the values of every added characteristic and descriptor
+    fn database(&self) -> GattDatabase;
+
+    /// Choose how values larger than the negotiated MTU allows are sent, see [`OversizePolicy`]
+    fn set_oversize_policy(&mut self, policy: OversizePolicy);
+
+    /// Push a value to every central subscribed to the characteristic, with a notification
+    /// or an indication depending on its properties, returns how many received it
+    fn update_characteristic(
+        &mut self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+    ) -> impl Future<Output = Result<usize, Error>> + Send;
+
+    /// Send an indication to every central subscribed to the characteristic, fails when
+    /// none is. What a successful result means depends on the backend:
+    /// - BlueZ resolves once each central confirmed it, and fails on disconnect or when
+    ///   `timeout` elapses first. It only confirms indications of characteristics which
+    ///   do not also notify, on others this fails with `IndicationNotConfirmable`
+    /// - CoreBluetooth reports no confirmations, it resolves once the value was queued
+    ///   for sending, `timeout` only bounds the wait for room in the queue
+    /// - The mock resolves once the value reached the channel of every virtual central
+    fn indicate(
+        &mut self,
+        service: Uuid,
+        characteristic: Uuid,
+        value: Vec<u8>,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+}
@@ -0,0 +1,305 @@
+#![cfg(feature = "mock")]
+
+use std::time::Duration;
+
+use ble_peripheral_rust::{
+    adv::advertisement_data::AdvertisementData,
+    gatt::{
+        characteristic::Characteristic,
+        notification::{ClientConfiguration, OversizePolicy},
+        peripheral_event::PeripheralEvent,
+        properties::CharacteristicProperty,
+        service::Service,
+    },
+    mock::{Peripheral, VirtualCentral},
+    AttError, PeripheralBackend, SdpShortUuid,
+};
+use tokio::sync::mpsc::{channel, unbounded_channel, Receiver, UnboundedReceiver};
+use uuid::Uuid;
+
+/// What the application saw of a request, without its responder
+#[derive(Debug, PartialEq)]
+enum Seen {
+    Connect(String),
+    Disconnect(String),
+    Subscribe(Option<String>, ClientConfiguration),
+    Unsubscribe(Option<String>),
+    Read(u16),
+    Write(Vec<u8>),
+    CancelWrite,
+}
+
+fn service_uuid() -> Uuid {
+    Uuid::from_sdp_short_uuid(0x1234_u16)
+}
+
+fn char_uuid() -> Uuid {
+    Uuid::from_sdp_short_uuid(0x2A3D_u16)
+}
+
+fn indicate_uuid() -> Uuid {
+    Uuid::from_sdp_short_uuid(0x2A3E_u16)
+}
+
+/// Answer every read with `value` and accept every write which is not empty,
+/// reporting the requests in the order they arrived
+fn serve(mut receiver_rx: Receiver<PeripheralEvent>, value: Vec<u8>) -> UnboundedReceiver<Seen> {
+    let (seen_tx, seen_rx) = unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(event) = receiver_rx.recv().await {
+            let seen = match event {
+                PeripheralEvent::DidConnect { client } => Seen::Connect(client.id),
+                PeripheralEvent::DidDisconnect { client, .. } => Seen::Disconnect(client.id),
+                PeripheralEvent::DidSubscribeToCharacteristic {
+                    client,
+                    configuration,
+                    ..
+                } => Seen::Subscribe(client.map(|client| client.id), configuration),
+                PeripheralEvent::DidUnsubscribeFromCharacteristic { client, .. } => {
+                    Seen::Unsubscribe(client.map(|client| client.id))
+                }
+                PeripheralEvent::DidReceiveReadRequest {
+                    offset, responder, ..
+                } => {
+                    let _ = responder.send(Ok(value.clone()));
+                    Seen::Read(offset)
+                }
+                PeripheralEvent::DidReceiveWriteRequest {
+                    value, responder, ..
+                } => {
+                    let response = match value.is_empty() {
+                        true => Err(AttError::InvalidAttributeValueLength),
+                        false => Ok(()),
+                    };
+                    let _ = responder.send(response);
+                    Seen::Write(value)
+                }
+                PeripheralEvent::DidCancelPreparedWrite { .. } => Seen::CancelWrite,
+                _ => continue,
+            };
+            if seen_tx.send(seen).is_err() {
+                return;
+            }
+        }
+    });
+    seen_rx
+}
+
+/// Advertising peripheral with a notifying and an indicating characteristic,
+/// and a central connected to it
+async fn connected(value: Vec<u8>) -> (Peripheral, VirtualCentral, UnboundedReceiver<Seen>) {
+    let (sender_tx, receiver_rx) = channel::<PeripheralEvent>(16);
+    let mut peripheral = Peripheral::new(sender_tx).await.unwrap();
+    let mut seen = serve(receiver_rx, value);
+
+    let service = Service {
+        uuid: service_uuid(),
+        primary: true,
+        characteristics: vec![
+            Characteristic {
+                uuid: char_uuid(),
+                ..Default::default()
+            },
+            Characteristic {
+                uuid: indicate_uuid(),
+                properties: vec![CharacteristicProperty::Indicate],
+                ..Default::default()
+            },
+        ],
+    };
+    peripheral.add_service(&service).await.unwrap();
+    peripheral
+        .start_advertising(&AdvertisementData::default())
+        .await
+        .unwrap();
+
+    let mut central = peripheral.virtual_central("central-1");
+    central.connect().await.unwrap();
+    assert_eq!(
+        seen.recv().await,
+        Some(Seen::Connect("central-1".to_string()))
+    );
+    (peripheral, central, seen)
+}
+
+#[tokio::test]
+async fn connect_requires_advertising() {
+    let (sender_tx, receiver_rx) = channel::<PeripheralEvent>(16);
+    let peripheral = Peripheral::new(sender_tx).await.unwrap();
+    let _seen = serve(receiver_rx, vec![]);
+
+    let mut central = peripheral.virtual_central("central-1");
+    assert!(central.connect().await.is_err());
+    assert!(!central.is_connected());
+}
+
+#[tokio::test]
+async fn disconnect_ends_subscriptions_and_requests() {
+    let (_peripheral, mut central, mut seen) = connected(vec![]).await;
+    assert!(central.is_connected());
+    central
+        .subscribe(service_uuid(), char_uuid())
+        .await
+        .unwrap();
+    assert_eq!(
+        seen.recv().await,
+        Some(Seen::Subscribe(
+            Some("central-1".to_string()),
+            ClientConfiguration::NOTIFY
+        ))
+    );
+
+    central.disconnect().await.unwrap();
+    assert_eq!(
+        seen.recv().await,
+        Some(Seen::Unsubscribe(Some("central-1".to_string())))
+    );
+    assert_eq!(
+        seen.recv().await,
+        Some(Seen::Disconnect("central-1".to_string()))
+    );
+    assert!(!central.is_connected());
+    assert!(central.read(service_uuid(), char_uuid()).await.is_err());
+}
+
+#[tokio::test]
+async fn long_read_continues_with_read_blob_requests() {
+    let value: Vec<u8> = (0..50).collect();
+    let (_peripheral, central, mut seen) = connected(value.clone()).await;
+
+    // With the default MTU of 23 the value takes reads of 22, 22 and 6 bytes,
+    // the application answers the first one and the rest come from the cached value
+    assert_eq!(
+        central.read(service_uuid(), char_uuid()).await.unwrap(),
+        value
+    );
+    assert_eq!(seen.recv().await, Some(Seen::Read(0)));
+
+    let chunk = central
+        .read_at(service_uuid(), char_uuid(), 0)
+        .await
+        .unwrap();
+    assert_eq!(chunk, value[..22]);
+    let chunk = central
+        .read_at(service_uuid(), char_uuid(), 22)
+        .await
+        .unwrap();
+    assert_eq!(chunk, value[22..44]);
+    assert_eq!(seen.recv().await, Some(Seen::Read(0)));
+    assert!(seen.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn prepared_writes_reach_the_application_on_execute() {
+    let (_peripheral, mut central, mut seen) = connected(vec![]).await;
+
+    central
+        .prepare_write(service_uuid(), char_uuid(), 0, vec![1, 2, 3])
+        .unwrap();
+    central
+        .prepare_write(service_uuid(), char_uuid(), 3, vec![4, 5])
+        .unwrap();
+    assert!(seen.try_recv().is_err());
+
+    central.execute_write().await.unwrap();
+    assert_eq!(seen.recv().await, Some(Seen::Write(vec![1, 2, 3, 4, 5])));
+}
+
+#[tokio::test]
+async fn cancelled_prepared_writes_never_reach_the_application() {
+    let (_peripheral, mut central, mut seen) = connected(vec![]).await;
+
+    central
+        .prepare_write(service_uuid(), char_uuid(), 0, vec![1, 2, 3])
+        .unwrap();
+    central.cancel_write().await.unwrap();
+    assert_eq!(seen.recv().await, Some(Seen::CancelWrite));
+
+    // Nothing is left to execute after the cancel
+    central.execute_write().await.unwrap();
+    central
+        .write(service_uuid(), char_uuid(), vec![])
+        .await
+        .unwrap_err();
+    assert_eq!(seen.recv().await, Some(Seen::Write(vec![])));
+}
+
+#[tokio::test]
+async fn subscription_uses_the_negotiated_mtu() {
+    let (mut peripheral, mut central, _seen) = connected(vec![]).await;
+    central.set_mtu(50);
+    let mut values = central
+        .subscribe(service_uuid(), char_uuid())
+        .await
+        .unwrap();
+
+    let value = vec![7; 47];
+    let delivered = peripheral
+        .update_characteristic(service_uuid(), char_uuid(), value.clone())
+        .await
+        .unwrap();
+    assert_eq!(delivered, 1);
+    assert_eq!(values.recv().await, Some(value));
+}
+
+#[tokio::test]
+async fn oversize_values_are_rejected_by_default() {
+    let (mut peripheral, central, _seen) = connected(vec![]).await;
+    let mut values = central
+        .subscribe(service_uuid(), char_uuid())
+        .await
+        .unwrap();
+
+    let result = peripheral
+        .update_characteristic(service_uuid(), char_uuid(), vec![7; 30])
+        .await;
+    assert!(result.is_err());
+    assert!(values.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn oversize_values_are_split_when_asked_to() {
+    let (mut peripheral, central, _seen) = connected(vec![]).await;
+    let mut values = central
+        .subscribe(service_uuid(), char_uuid())
+        .await
+        .unwrap();
+    peripheral.set_oversize_policy(OversizePolicy::Split);
+
+    let value: Vec<u8> = (0..30).collect();
+    let delivered = peripheral
+        .update_characteristic(service_uuid(), char_uuid(), value.clone())
+        .await
+        .unwrap();
+    assert_eq!(delivered, 1);
+    assert_eq!(values.recv().await, Some(value[..20].to_vec()));
+    assert_eq!(values.recv().await, Some(value[20..].to_vec()));
+}
+
+#[tokio::test]
+async fn indication_needs_a_subscriber() {
+    let (mut peripheral, central, mut seen) = connected(vec![]).await;
+    let timeout = Duration::from_secs(1);
+
+    let result = peripheral
+        .indicate(service_uuid(), indicate_uuid(), vec![1], timeout)
+        .await;
+    assert!(result.is_err());
+
+    let mut values = central
+        .subscribe(service_uuid(), indicate_uuid())
+        .await
+        .unwrap();
+    assert_eq!(
+        seen.recv().await,
+        Some(Seen::Subscribe(
+            Some("central-1".to_string()),
+            ClientConfiguration::INDICATE
+        ))
+    );
+    peripheral
+        .indicate(service_uuid(), indicate_uuid(), vec![1], timeout)
+        .await
+        .unwrap();
+    assert_eq!(values.recv().await, Some(vec![1]));
+}